@@ -0,0 +1,73 @@
+//! Benchmarks of converting line endings in memory.
+//!
+//! Conversions of only line endings take the chunked path, and scans classify line endings a
+//! vector at a time. Recognizing Unicode separators makes the same conversion or scan of ASCII
+//! content go through the per-unit tokens, for comparison.
+
+use std::io;
+
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use newl::{Conversion, Eol};
+
+const LEN: usize = 16 * 1024 * 1024;
+
+/// ASCII text of about `LEN` bytes with lines ending in `endings`, in turn.
+fn text(endings: &[&str]) -> Vec<u8> {
+    let mut text = Vec::with_capacity(LEN + 100);
+    let mut endings = endings.iter().cycle();
+    while text.len() < LEN {
+        text.extend_from_slice(b"The quick brown fox jumps over the lazy dog, 0123456789.");
+        text.extend_from_slice(endings.next().unwrap().as_bytes());
+    }
+    text
+}
+
+fn convert(c: &mut Criterion) {
+    let inputs = [
+        ("lf", text(&["\n"])),
+        ("crlf", text(&["\r\n"])),
+        ("mixed", text(&["\n", "\r\n", "\r"])),
+    ];
+    for eol in [Eol::Lf, Eol::Crlf] {
+        let mut group = c.benchmark_group(format!("convert to {eol}"));
+        group
+            .throughput(Throughput::Bytes(LEN as u64))
+            .sample_size(10);
+        for (name, input) in &inputs {
+            let chunked = Conversion::new(eol);
+            let tokens = Conversion {
+                unicode: true,
+                ..chunked
+            };
+            group.bench_with_input(BenchmarkId::new("chunked", name), input, |b, input| {
+                b.iter(|| newl::convert(input.as_slice(), io::sink(), chunked))
+            });
+            group.bench_with_input(BenchmarkId::new("tokens", name), input, |b, input| {
+                b.iter(|| newl::convert(input.as_slice(), io::sink(), tokens))
+            });
+        }
+        group.finish();
+    }
+
+    let mut group = c.benchmark_group("scan");
+    group
+        .throughput(Throughput::Bytes(LEN as u64))
+        .sample_size(10);
+    for (name, input) in &inputs {
+        let classified = Conversion::new(Eol::Lf);
+        let tokens = Conversion {
+            unicode: true,
+            ..classified
+        };
+        group.bench_with_input(BenchmarkId::new("classified", name), input, |b, input| {
+            b.iter(|| newl::scan(input.as_slice(), classified))
+        });
+        group.bench_with_input(BenchmarkId::new("tokens", name), input, |b, input| {
+            b.iter(|| newl::scan(input.as_slice(), tokens))
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, convert);
+criterion_main!(benches);
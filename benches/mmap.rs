@@ -0,0 +1,73 @@
+//! Benchmarks of converting large files by reading them in chunks or memory-mapping them.
+//!
+//! Inputs are 1 GiB files by default, set `NEWL_BENCH_MIB` to change their size.
+
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+use criterion::{Criterion, Throughput, criterion_group, criterion_main};
+use memmap2::Mmap;
+use newl::{Conversion, Eol};
+
+/// Size of the input files in MiB.
+fn len() -> u64 {
+    std::env::var("NEWL_BENCH_MIB")
+        .ok()
+        .and_then(|mib| mib.parse().ok())
+        .unwrap_or(1024)
+        * 1024
+        * 1024
+}
+
+/// Write a file of ASCII text of about `len` bytes with lines ending in `eol`.
+fn write_text(path: &Path, len: u64, eol: Eol) -> io::Result<()> {
+    let mut file = BufWriter::new(File::create(path)?);
+    let line = b"The quick brown fox jumps over the lazy dog, 0123456789.";
+    let mut written = 0;
+    while written < len {
+        file.write_all(line)?;
+        file.write_all(eol.as_bytes())?;
+        written += (line.len() + eol.as_bytes().len()) as u64;
+    }
+    file.flush()
+}
+
+fn map(path: &Path) -> Mmap {
+    let file = File::open(path).unwrap();
+    // SAFETY: The benchmark files are not modified while mapped.
+    unsafe { Mmap::map(&file).unwrap() }
+}
+
+fn large_files(c: &mut Criterion) {
+    let len = len();
+    let dir = tempfile::tempdir().unwrap();
+    let crlf = dir.path().join("crlf.txt");
+    let lf = dir.path().join("lf.txt");
+    write_text(&crlf, len, Eol::Crlf).unwrap();
+    write_text(&lf, len, Eol::Lf).unwrap();
+    let conversion = Conversion::new(Eol::Lf);
+
+    let mut group = c.benchmark_group("convert CRLF file to LF");
+    group.throughput(Throughput::Bytes(len)).sample_size(10);
+    group.bench_function("read", |b| {
+        b.iter(|| newl::convert(File::open(&crlf).unwrap(), io::sink(), conversion).unwrap())
+    });
+    group.bench_function("mmap", |b| {
+        b.iter(|| newl::convert_slice_to(&map(&crlf), io::sink(), conversion).unwrap())
+    });
+    group.finish();
+
+    let mut group = c.benchmark_group("check LF file");
+    group.throughput(Throughput::Bytes(len)).sample_size(10);
+    group.bench_function("read", |b| {
+        b.iter(|| newl::convert(File::open(&lf).unwrap(), io::sink(), conversion).unwrap())
+    });
+    group.bench_function("mmap", |b| {
+        b.iter(|| newl::is_unchanged(&map(&lf), conversion))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, large_files);
+criterion_main!(benches);
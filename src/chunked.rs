@@ -0,0 +1,259 @@
+//! Conversion of line endings in single byte content fed in large chunks, for conversions that
+//! change nothing else. Bytes between line endings that need no change are copied in bulk.
+
+use std::io::{self, Write};
+
+use memchr::memchr2;
+
+use crate::{CR, Eol, FinalNewline, LF, LoneCr, LoneCrs, Result};
+
+/// Converts line endings in content fed in chunks with [`Chunked::push`].
+/// Line breaks at the end of a chunk are held back, as they may end the content, and a `CR` at
+/// the end of a chunk may be followed by an `LF` at the start of the next one.
+pub struct Chunked {
+    eol: Eol,
+    lone_cr: LoneCr,
+    /// Number of line breaks held back.
+    held: usize,
+    /// Whether the last held byte is a `CR`, so that an `LF` after it ends the same line. It is
+    /// counted as a line break right away only if lone `CR`s are converted.
+    cr: bool,
+    /// Whether any content other than line breaks has been written.
+    started: bool,
+    /// Number of bytes converted so far, for the offsets of lone `CR`s.
+    offset: u64,
+    lone_crs: LoneCrs,
+}
+
+impl Chunked {
+    pub fn new(eol: Eol, lone_cr: LoneCr) -> Self {
+        Self {
+            eol,
+            lone_cr,
+            held: 0,
+            cr: false,
+            started: false,
+            offset: 0,
+            lone_crs: LoneCrs::default(),
+        }
+    }
+
+    /// Convert the next chunk of content into `writer`.
+    pub fn push(&mut self, chunk: &[u8], writer: &mut impl Write) -> io::Result<()> {
+        let is_content = |byte: &u8| *byte != CR && *byte != LF;
+        let Some(start) = chunk.iter().position(is_content) else {
+            return self.hold(chunk, writer);
+        };
+        let end = chunk.iter().rposition(is_content).map_or(start, |i| i + 1);
+        self.hold(&chunk[..start], writer)?;
+        if self.cr && self.lone_cr != LoneCr::Convert {
+            self.write_lone(writer)?;
+        }
+        self.cr = false;
+        self.release(writer)?;
+        self.convert(&chunk[start..end], writer)?;
+        self.started = true;
+        self.hold(&chunk[end..], writer)
+    }
+
+    /// Count a run of line break bytes as held back. A held `CR` that turns out to be lone is
+    /// written as content unless lone `CR`s are converted.
+    fn hold(&mut self, breaks: &[u8], writer: &mut impl Write) -> io::Result<()> {
+        let converted = self.lone_cr == LoneCr::Convert;
+        for &byte in breaks {
+            let after_cr = std::mem::replace(&mut self.cr, byte == CR);
+            if byte == LF {
+                // An `LF` after a `CR` completes a `CRLF`, which is already counted if lone `CR`s
+                // are line breaks.
+                if !(after_cr && converted) {
+                    self.held += 1;
+                }
+            } else if converted {
+                self.held += 1;
+            } else if after_cr {
+                self.write_lone(writer)?;
+            }
+            self.offset += 1;
+        }
+        io::Result::Ok(())
+    }
+
+    /// Write the line breaks held back, as content follows them.
+    fn release(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        for _ in 0..self.held {
+            writer.write_all(self.eol.as_bytes())?;
+        }
+        self.held = 0;
+        io::Result::Ok(())
+    }
+
+    /// Write the lone `CR` before the current offset as content, after the line breaks held back.
+    fn write_lone(&mut self, writer: &mut impl Write) -> io::Result<()> {
+        if self.lone_cr == LoneCr::Error {
+            self.lone_crs.push(self.offset - 1);
+        }
+        self.release(writer)?;
+        writer.write_all(&[CR])?;
+        self.started = true;
+        io::Result::Ok(())
+    }
+
+    /// Convert content that starts and ends with other bytes than line breaks, so that every
+    /// `CR` in it is followed by another byte.
+    fn convert(&mut self, content: &[u8], writer: &mut impl Write) -> io::Result<()> {
+        let (mut run, mut pos) = (0, 0);
+        while let Some(i) = memchr2(CR, LF, &content[pos..]) {
+            let at = pos + i;
+            let eol = match (content[at], content[at + 1]) {
+                (LF, _) => Eol::Lf,
+                (_, LF) => Eol::Crlf,
+                _ => Eol::Cr,
+            };
+            let len = eol.as_bytes().len();
+            if eol == Eol::Cr && self.lone_cr != LoneCr::Convert {
+                if self.lone_cr == LoneCr::Error {
+                    self.lone_crs.push(self.offset + at as u64);
+                }
+            } else if eol != self.eol {
+                writer.write_all(&content[run..at])?;
+                writer.write_all(self.eol.as_bytes())?;
+                run = at + len;
+            }
+            pos = at + len;
+        }
+        self.offset += content.len() as u64;
+        writer.write_all(&content[run..])
+    }
+
+    /// Write the line breaks held back at the end of content as set by `final_newline`.
+    /// Returns the change made to the final newline, if any, or an error if lone `CR`s are
+    /// rejected and there are any.
+    pub fn finish(
+        mut self,
+        final_newline: FinalNewline,
+        writer: &mut impl Write,
+    ) -> Result<Option<FinalNewline>> {
+        if self.cr && self.lone_cr != LoneCr::Convert {
+            self.write_lone(writer)?;
+        }
+        let change = match final_newline {
+            FinalNewline::Strip => (self.held > 0).then_some(FinalNewline::Strip),
+            FinalNewline::Ensure if self.started && self.held == 0 => {
+                writer.write_all(self.eol.as_bytes())?;
+                Some(FinalNewline::Ensure)
+            },
+            _ => {
+                self.release(writer)?;
+                None
+            },
+        };
+        self.lone_crs.check()?;
+        Ok(change)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn convert(
+        eol: Eol,
+        final_newline: FinalNewline,
+        chunks: &[&[u8]],
+    ) -> (String, Option<FinalNewline>) {
+        convert_with(eol, LoneCr::Convert, final_newline, chunks)
+    }
+
+    fn convert_with(
+        eol: Eol,
+        lone_cr: LoneCr,
+        final_newline: FinalNewline,
+        chunks: &[&[u8]],
+    ) -> (String, Option<FinalNewline>) {
+        let mut chunked = Chunked::new(eol, lone_cr);
+        let mut out = Vec::new();
+        for chunk in chunks {
+            chunked.push(chunk, &mut out).unwrap();
+        }
+        let change = chunked.finish(final_newline, &mut out).unwrap();
+        (String::from_utf8(out).unwrap(), change)
+    }
+
+    #[test]
+    fn chunk_boundaries() {
+        let input = b"\r\na\r\rb\n\r\nc\r\n\r";
+        for (eol, expected) in [
+            (Eol::Lf, "\na\n\nb\n\nc\n\n"),
+            (Eol::Crlf, "\r\na\r\n\r\nb\r\n\r\nc\r\n\r\n"),
+            (Eol::Cr, "\ra\r\rb\r\rc\r\r"),
+        ] {
+            for split in 0..=input.len() {
+                let (a, b) = input.split_at(split);
+                let output = convert(eol, FinalNewline::Keep, &[a, b]);
+                assert_eq!(output, (expected.into(), None), "{eol} split at {split}");
+            }
+            let bytes = input.chunks(1).collect::<Vec<_>>();
+            assert_eq!(convert(eol, FinalNewline::Keep, &bytes).0, expected);
+        }
+    }
+
+    #[test]
+    fn final_newline() {
+        use FinalNewline::*;
+        assert_eq!(
+            convert(Eol::Lf, Strip, &[b"a\r", b"\n\r"]),
+            ("a".into(), Some(Strip))
+        );
+        assert_eq!(convert(Eol::Lf, Strip, &[b"a"]), ("a".into(), None));
+        assert_eq!(
+            convert(Eol::Lf, Ensure, &[b"a\r", b"b"]),
+            ("a\nb\n".into(), Some(Ensure))
+        );
+        assert_eq!(
+            convert(Eol::Lf, Ensure, &[b"a\r", b"\n"]),
+            ("a\n".into(), None)
+        );
+        assert_eq!(convert(Eol::Lf, Ensure, &[b"\r\n"]), ("\n".into(), None));
+        assert_eq!(convert(Eol::Lf, Ensure, &[b""]), ("".into(), None));
+    }
+
+    #[test]
+    fn lone_cr() {
+        let input = b"\ra\r\rb\n\r\nc\r\r\n\r";
+        for (eol, expected) in [
+            (Eol::Lf, "\ra\r\rb\n\nc\r\n\r"),
+            (Eol::Crlf, "\ra\r\rb\r\n\r\nc\r\r\n\r"),
+        ] {
+            for split in 0..=input.len() {
+                let (a, b) = input.split_at(split);
+                let output = convert_with(eol, LoneCr::Keep, FinalNewline::Keep, &[a, b]);
+                assert_eq!(output, (expected.into(), None), "{eol} split at {split}");
+            }
+        }
+        assert_eq!(
+            convert_with(Eol::Lf, LoneCr::Keep, FinalNewline::Ensure, &[b"a\n\r"]),
+            ("a\n\r\n".into(), Some(FinalNewline::Ensure))
+        );
+        assert_eq!(
+            convert_with(Eol::Lf, LoneCr::Keep, FinalNewline::Strip, &[b"a\r\n\r\n"]),
+            ("a".into(), Some(FinalNewline::Strip))
+        );
+
+        for split in 0..=input.len() {
+            let (a, b) = input.split_at(split);
+            let mut chunked = Chunked::new(Eol::Lf, LoneCr::Error);
+            chunked.push(a, &mut io::sink()).unwrap();
+            chunked.push(b, &mut io::sink()).unwrap();
+            match chunked.finish(FinalNewline::Keep, &mut io::sink()) {
+                Err(crate::Error::LoneCr { offsets, count }) => {
+                    assert_eq!(
+                        (offsets, count),
+                        (vec![0, 2, 3, 9, 12], 5),
+                        "split at {split}"
+                    )
+                },
+                result => panic!("{result:?}"),
+            }
+        }
+    }
+}
@@ -0,0 +1,246 @@
+//! Classification of line endings in single byte content, counting them a vector at a time where
+//! the target supports it, with a portable scalar fallback.
+
+use crate::{CR, Eol, LF};
+
+/// Counts of line ending sequences in a buffer.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Histogram {
+    pub lf: usize,
+    pub crlf: usize,
+    pub cr: usize,
+}
+
+impl Histogram {
+    /// Whether all line endings, if any, are `eol`.
+    pub fn only(&self, eol: Eol) -> bool {
+        match eol {
+            Eol::Lf => self.crlf == 0 && self.cr == 0,
+            Eol::Crlf => self.lf == 0 && self.cr == 0,
+            Eol::Cr => self.lf == 0 && self.crlf == 0,
+        }
+    }
+}
+
+impl std::ops::Add for Histogram {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            lf: self.lf + other.lf,
+            crlf: self.crlf + other.crlf,
+            cr: self.cr + other.cr,
+        }
+    }
+}
+
+/// Classifies the line endings of content fed in buffers with [`Classifier::push`].
+/// A `CR` at the end of a buffer is counted with the next one, as it may start a `CRLF`.
+#[derive(Debug, Default)]
+pub struct Classifier {
+    /// Whether the last buffer ended with a `CR`.
+    cr: bool,
+}
+
+impl Classifier {
+    /// Classify the line endings of the next buffer.
+    pub fn push(&mut self, buf: &[u8]) -> Histogram {
+        let Some((&last, _)) = buf.split_last() else {
+            return Histogram::default();
+        };
+        let mut histogram = Histogram::default();
+        let mut buf = buf;
+        if self.cr {
+            if buf[0] == LF {
+                histogram.crlf += 1;
+                buf = &buf[1..];
+            } else {
+                histogram.cr += 1;
+            }
+        }
+        let counts = count(buf);
+        self.cr = last == CR;
+        histogram.crlf += counts.crlf;
+        histogram.lf += counts.lf - counts.crlf;
+        histogram.cr += counts.cr - counts.crlf - usize::from(self.cr);
+        histogram
+    }
+
+    /// Classify a `CR` held back at the end of content.
+    pub fn finish(self) -> Histogram {
+        Histogram {
+            cr: usize::from(self.cr),
+            ..Histogram::default()
+        }
+    }
+}
+
+/// Counts of `CR` and `LF` bytes, and of `CR` bytes directly followed by an `LF`.
+#[derive(Debug, Default, PartialEq, Eq)]
+struct Counts {
+    cr: usize,
+    lf: usize,
+    crlf: usize,
+}
+
+impl Counts {
+    /// Add the counts of a block from bit masks of its `CR` and `LF` bytes, and of the `LF` bytes
+    /// one byte later.
+    #[cfg(target_arch = "x86_64")]
+    fn add(&mut self, cr: u32, lf: u32, next_lf: u32) {
+        self.cr += cr.count_ones() as usize;
+        self.lf += lf.count_ones() as usize;
+        self.crlf += (cr & next_lf).count_ones() as usize;
+    }
+}
+
+impl std::ops::Add for Counts {
+    type Output = Self;
+
+    fn add(self, other: Self) -> Self {
+        Self {
+            cr: self.cr + other.cr,
+            lf: self.lf + other.lf,
+            crlf: self.crlf + other.crlf,
+        }
+    }
+}
+
+#[cfg(target_arch = "x86_64")]
+fn count(buf: &[u8]) -> Counts {
+    if is_x86_feature_detected!("avx2") {
+        // SAFETY: AVX2 is available, as just detected.
+        unsafe { x86::count_avx2(buf) }
+    } else {
+        x86::count_sse2(buf)
+    }
+}
+
+#[cfg(not(target_arch = "x86_64"))]
+fn count(buf: &[u8]) -> Counts {
+    count_scalar(buf)
+}
+
+fn count_scalar(buf: &[u8]) -> Counts {
+    let mut counts = Counts::default();
+    for (i, &byte) in buf.iter().enumerate() {
+        if byte == CR {
+            counts.cr += 1;
+            counts.crlf += usize::from(buf.get(i + 1) == Some(&LF));
+        } else if byte == LF {
+            counts.lf += 1;
+        }
+    }
+    counts
+}
+
+/// Counting with SSE2, which is part of the x86-64 baseline, or with AVX2 if it is detected.
+/// Each block of bytes is compared to the block one byte later too, to find `CRLF` pairs.
+#[cfg(target_arch = "x86_64")]
+mod x86 {
+    use std::arch::x86_64::*;
+
+    use super::{Counts, count_scalar};
+    use crate::{CR, LF};
+
+    pub fn count_sse2(buf: &[u8]) -> Counts {
+        const LANES: usize = 16;
+        let mut counts = Counts::default();
+        let mut i = 0;
+        while i + LANES < buf.len() {
+            // SAFETY: Both loads read 16 bytes within `buf`, and have no alignment requirement.
+            // SSE2 is available on every x86-64 target.
+            let (cr, lf, next_lf) = unsafe {
+                let block = _mm_loadu_si128(buf.as_ptr().add(i).cast());
+                let next = _mm_loadu_si128(buf.as_ptr().add(i + 1).cast());
+                (
+                    _mm_movemask_epi8(_mm_cmpeq_epi8(block, _mm_set1_epi8(CR as i8))),
+                    _mm_movemask_epi8(_mm_cmpeq_epi8(block, _mm_set1_epi8(LF as i8))),
+                    _mm_movemask_epi8(_mm_cmpeq_epi8(next, _mm_set1_epi8(LF as i8))),
+                )
+            };
+            counts.add(cr as u32, lf as u32, next_lf as u32);
+            i += LANES;
+        }
+        counts + count_scalar(&buf[i..])
+    }
+
+    #[target_feature(enable = "avx2")]
+    pub fn count_avx2(buf: &[u8]) -> Counts {
+        const LANES: usize = 32;
+        let mut counts = Counts::default();
+        let mut i = 0;
+        while i + LANES < buf.len() {
+            // SAFETY: Both loads read 32 bytes within `buf`, and have no alignment requirement.
+            let (cr, lf, next_lf) = unsafe {
+                let block = _mm256_loadu_si256(buf.as_ptr().add(i).cast());
+                let next = _mm256_loadu_si256(buf.as_ptr().add(i + 1).cast());
+                (
+                    _mm256_movemask_epi8(_mm256_cmpeq_epi8(block, _mm256_set1_epi8(CR as i8))),
+                    _mm256_movemask_epi8(_mm256_cmpeq_epi8(block, _mm256_set1_epi8(LF as i8))),
+                    _mm256_movemask_epi8(_mm256_cmpeq_epi8(next, _mm256_set1_epi8(LF as i8))),
+                )
+            };
+            counts.add(cr as u32, lf as u32, next_lf as u32);
+            i += LANES;
+        }
+        counts + count_scalar(&buf[i..])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vectorized() {
+        // Pseudo-random bytes, mostly line breaks, of every length around the vector width.
+        let mut state = 0x2545_F491_u32;
+        let bytes = (0..300)
+            .map(|_| {
+                state ^= state << 13;
+                state ^= state >> 17;
+                state ^= state << 5;
+                [CR, LF, b'a'][state as usize % 3]
+            })
+            .collect::<Vec<_>>();
+        for start in 0..20 {
+            for end in start..bytes.len() {
+                let buf = &bytes[start..end];
+                assert_eq!(count(buf), count_scalar(buf), "{buf:?}");
+                #[cfg(target_arch = "x86_64")]
+                assert_eq!(x86::count_sse2(buf), count_scalar(buf), "{buf:?}");
+            }
+        }
+    }
+
+    fn classify(buf: &[u8]) -> Histogram {
+        let mut classifier = Classifier::default();
+        let histogram = classifier.push(buf);
+        histogram + classifier.finish()
+    }
+
+    #[test]
+    fn histogram() {
+        let input = b"a\r\nb\rc\n\r\r\n\n\r";
+        let expected = Histogram {
+            lf: 2,
+            crlf: 2,
+            cr: 3,
+        };
+        assert_eq!(classify(input), expected);
+        for split in 0..=input.len() {
+            let (a, b) = input.split_at(split);
+            let mut classifier = Classifier::default();
+            let histogram = classifier.push(a) + classifier.push(b) + classifier.push(b"");
+            assert_eq!(
+                histogram + classifier.finish(),
+                expected,
+                "split at {split}"
+            );
+        }
+        assert!(classify(b"a\r\n\r\n").only(Eol::Crlf));
+        assert!(!classify(b"a\r\n\n").only(Eol::Crlf));
+        assert!(classify(b"").only(Eol::Cr));
+    }
+}
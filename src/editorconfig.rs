@@ -0,0 +1,440 @@
+//! Resolution of the `end_of_line` property from `.editorconfig` files.
+//!
+//! See <https://spec.editorconfig.org> for the file format and glob semantics.
+
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, io};
+
+use newl::Eol;
+
+const FILENAME: &str = ".editorconfig";
+
+/// Resolves `end_of_line` for files, caching parsed `.editorconfig` files per directory.
+#[derive(Debug, Default)]
+pub struct EditorConfig {
+    cache: HashMap<PathBuf, Option<Rc<ConfigFile>>>,
+}
+
+impl EditorConfig {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the `end_of_line` property for a file.
+    /// Returns `None` if the property is not set (or is `unset`) for the file.
+    pub fn end_of_line(&mut self, path: &Path) -> io::Result<Option<Eol>> {
+        let path = std::path::absolute(path)?;
+        let mut files = Vec::new();
+        for dir in path.ancestors().skip(1) {
+            if let Some(file) = self.load(dir)? {
+                let root = file.root;
+                files.push((dir, file));
+                if root {
+                    break;
+                }
+            }
+        }
+
+        // Closer files take precedence, as do later sections within a file.
+        let mut eol = None;
+        for (dir, file) in files.iter().rev() {
+            let Some(relative) = path.strip_prefix(dir).ok().and_then(to_slash) else {
+                continue;
+            };
+            for section in &file.sections {
+                if let Some(value) = section.end_of_line
+                    && section.glob.is_match(&relative)
+                {
+                    eol = value;
+                }
+            }
+        }
+        Ok(eol)
+    }
+
+    fn load(&mut self, dir: &Path) -> io::Result<Option<Rc<ConfigFile>>> {
+        if let Some(file) = self.cache.get(dir) {
+            return Ok(file.clone());
+        }
+        let file = match fs::read_to_string(dir.join(FILENAME)) {
+            Ok(text) => Some(Rc::new(ConfigFile::parse(&text))),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound | io::ErrorKind::IsADirectory
+                ) =>
+            {
+                None
+            },
+            Err(e) => return Err(e),
+        };
+        self.cache.insert(dir.to_path_buf(), file.clone());
+        Ok(file)
+    }
+}
+
+/// Join path components with `/`, failing on non-UTF-8 components.
+fn to_slash(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+#[derive(Debug, Default)]
+struct ConfigFile {
+    root: bool,
+    sections: Vec<Section>,
+}
+
+#[derive(Debug)]
+struct Section {
+    glob: Glob,
+    /// `Some(None)` means that the property was explicitly `unset`.
+    end_of_line: Option<Option<Eol>>,
+}
+
+impl ConfigFile {
+    fn parse(text: &str) -> Self {
+        let mut file = Self::default();
+        for line in text.lines() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with(['#', ';']) {
+                continue;
+            }
+            if line.starts_with('[') && line.ends_with(']') {
+                let glob = Glob::new(&line[1..line.len() - 1]);
+                file.sections.push(Section {
+                    glob,
+                    end_of_line: None,
+                });
+                continue;
+            }
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+            let key = key.trim().to_ascii_lowercase();
+            let value = value.trim().to_ascii_lowercase();
+            match file.sections.last_mut() {
+                None if key == "root" => file.root = value == "true",
+                Some(section) if key == "end_of_line" => {
+                    section.end_of_line = match value.as_str() {
+                        "unset" => Some(None),
+                        value => value.parse().ok().map(Some),
+                    };
+                },
+                _ => {},
+            }
+        }
+        file
+    }
+}
+
+/// EditorConfig section glob.
+#[derive(Debug)]
+struct Glob {
+    tokens: Vec<Token>,
+    /// Globs without a `/` may match at any directory level.
+    anchored: bool,
+}
+
+#[derive(Debug, Clone)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `**`
+    Globstar,
+    /// `[name]` or `[!name]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+    /// `{s1,s2,s3}`
+    Alternatives(Vec<Vec<Token>>),
+    /// `{num1..num2}`
+    Range(i64, i64),
+}
+
+impl Glob {
+    fn new(pattern: &str) -> Self {
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut pos = 0;
+        let mut tokens = parse(&chars, &mut pos, false);
+        let anchored = has_separator(&tokens);
+        if let Some(Token::Char('/')) = tokens.first() {
+            tokens.remove(0);
+        }
+        Self { tokens, anchored }
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        let path = path.chars().collect::<Vec<_>>();
+        if self.anchored {
+            return matches(&self.tokens, &path);
+        }
+        // Try the whole path and every suffix that starts after a separator.
+        std::iter::once(0)
+            .chain(
+                path.iter()
+                    .enumerate()
+                    .filter(|(_, c)| **c == '/')
+                    .map(|(i, _)| i + 1),
+            )
+            .any(|start| matches(&self.tokens, &path[start..]))
+    }
+}
+
+fn has_separator(tokens: &[Token]) -> bool {
+    tokens.iter().any(|t| match t {
+        Token::Char('/') => true,
+        Token::Alternatives(alts) => alts.iter().any(|a| has_separator(a)),
+        _ => false,
+    })
+}
+
+/// Parse glob tokens until the end of input, or until an unnested `,` or `}` if `in_braces`.
+fn parse(chars: &[char], pos: &mut usize, in_braces: bool) -> Vec<Token> {
+    let mut tokens = Vec::new();
+    while let Some(&c) = chars.get(*pos) {
+        match c {
+            ',' | '}' if in_braces => break,
+            '\\' => {
+                *pos += 1;
+                if let Some(&c) = chars.get(*pos) {
+                    tokens.push(Token::Char(c));
+                    *pos += 1;
+                }
+            },
+            '?' => {
+                tokens.push(Token::Any);
+                *pos += 1;
+            },
+            '*' if chars.get(*pos + 1) == Some(&'*') => {
+                tokens.push(Token::Globstar);
+                *pos += 2;
+            },
+            '*' => {
+                tokens.push(Token::Star);
+                *pos += 1;
+            },
+            '[' => match parse_class(chars, *pos) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    *pos = end;
+                },
+                None => {
+                    tokens.push(Token::Char('['));
+                    *pos += 1;
+                },
+            },
+            '{' => match parse_braces(chars, *pos) {
+                Some((token, end)) => {
+                    tokens.push(token);
+                    *pos = end;
+                },
+                None => {
+                    tokens.push(Token::Char('{'));
+                    *pos += 1;
+                },
+            },
+            c => {
+                tokens.push(Token::Char(c));
+                *pos += 1;
+            },
+        }
+    }
+    tokens
+}
+
+/// Parse a character class starting at `[`, returning the token and the position after `]`.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut pos = start + 1;
+    let negated = chars.get(pos) == Some(&'!');
+    if negated {
+        pos += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = *chars.get(pos)?;
+        match c {
+            ']' if !first => return Some((Token::Class { negated, ranges }, pos + 1)),
+            '/' => return None,
+            _ => {
+                let c = match c {
+                    '\\' => {
+                        pos += 1;
+                        *chars.get(pos)?
+                    },
+                    c => c,
+                };
+                if chars.get(pos + 1) == Some(&'-') && chars.get(pos + 2).is_some_and(|&e| e != ']')
+                {
+                    ranges.push((c, chars[pos + 2]));
+                    pos += 3;
+                } else {
+                    ranges.push((c, c));
+                    pos += 1;
+                }
+            },
+        }
+        first = false;
+    }
+}
+
+/// Parse braces starting at `{`, returning the token and the position after `}`.
+fn parse_braces(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let close = {
+        let mut depth = 0;
+        let mut pos = start;
+        loop {
+            match chars.get(pos)? {
+                '\\' => pos += 1,
+                '{' => depth += 1,
+                '}' if depth == 1 => break pos,
+                '}' => depth -= 1,
+                _ => {},
+            }
+            pos += 1;
+        }
+    };
+
+    let inner = chars[start + 1..close].iter().collect::<String>();
+    if let Some((low, high)) = inner.split_once("..")
+        && let (Ok(low), Ok(high)) = (low.parse(), high.parse())
+    {
+        return Some((Token::Range(low, high), close + 1));
+    }
+
+    let mut pos = start + 1;
+    let mut alternatives = vec![parse(chars, &mut pos, true)];
+    while chars.get(pos) == Some(&',') {
+        pos += 1;
+        alternatives.push(parse(chars, &mut pos, true));
+    }
+    if pos != close || alternatives.len() < 2 {
+        // A single alternative is not a brace expansion, e.g. `{single}`.
+        return None;
+    }
+    Some((Token::Alternatives(alternatives), close + 1))
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Char(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+        Token::Any => text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..]),
+        Token::Star => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(rest, &text[i..])),
+        Token::Globstar => (0..=text.len()).any(|i| matches(rest, &text[i..])),
+        Token::Class { negated, ranges } => {
+            text.first().is_some_and(|&c| {
+                c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }) && matches(rest, &text[1..])
+        },
+        Token::Alternatives(alternatives) => alternatives.iter().any(|alt| {
+            let tokens = alt.iter().chain(rest).cloned().collect::<Vec<_>>();
+            matches(&tokens, text)
+        }),
+        Token::Range(low, high) => {
+            let sign = usize::from(text.first() == Some(&'-'));
+            let digits = text[sign..]
+                .iter()
+                .take_while(|c| c.is_ascii_digit())
+                .count();
+            (1..=digits).any(|len| {
+                let number = text[..sign + len].iter().collect::<String>();
+                number.parse::<i64>().is_ok_and(|n| *low <= n && n <= *high)
+                    && matches(rest, &text[sign + len..])
+            })
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Glob::new(pattern).is_match(path)
+    }
+
+    #[test]
+    fn globs() {
+        assert!(is_match("*", "a.txt"));
+        assert!(is_match("*", "dir/a.txt"));
+        assert!(is_match("*.bat", "scripts/build.bat"));
+        assert!(!is_match("*.bat", "build.bat.txt"));
+        assert!(is_match("*.{js,py}", "src/main.py"));
+        assert!(!is_match("*.{js,py}", "src/main.rs"));
+        assert!(is_match("lib/**.js", "lib/a/b/c.js"));
+        assert!(!is_match("lib/*.js", "lib/a/c.js"));
+        assert!(!is_match("lib/*.js", "src/lib/c.js"));
+        assert!(is_match("/lib/*.js", "lib/c.js"));
+        assert!(is_match("{a,b/c}.txt", "b/c.txt"));
+        assert!(!is_match("{a,b/c}.txt", "x/a.txt"));
+        assert!(is_match("file[0-9].txt", "file7.txt"));
+        assert!(!is_match("file[!0-9].txt", "file7.txt"));
+        assert!(is_match("v{1..12}.txt", "v10.txt"));
+        assert!(!is_match("v{1..12}.txt", "v13.txt"));
+        assert!(is_match("{single}", "{single}"));
+        assert!(is_match("a\\*b", "a*b"));
+        assert!(!is_match("a\\*b", "axb"));
+        assert!(is_match("Makefile", "sub/Makefile"));
+    }
+
+    #[test]
+    fn parse_sections() {
+        let file = ConfigFile::parse(
+            "root = true\n\n[*]\nend_of_line = lf\n\n# comment\n[*.bat]\nEND_OF_LINE = \
+             CRLF\n\n[vendor/**]\nend_of_line = unset\n",
+        );
+        assert!(file.root);
+        assert_eq!(file.sections.len(), 3);
+        assert_eq!(file.sections[0].end_of_line, Some(Some(Eol::Lf)));
+        assert_eq!(file.sections[1].end_of_line, Some(Some(Eol::Crlf)));
+        assert_eq!(file.sections[2].end_of_line, Some(None));
+    }
+
+    #[test]
+    fn resolve() {
+        let dir = std::env::temp_dir().join(format!("newl-editorconfig-{}", std::process::id()));
+        let sub = dir.join("sub");
+        fs::create_dir_all(&sub).unwrap();
+        fs::write(
+            dir.join(FILENAME),
+            "root = true\n[*]\nend_of_line = lf\n[*.bat]\nend_of_line = crlf\n",
+        )
+        .unwrap();
+        fs::write(
+            sub.join(FILENAME),
+            "[*.txt]\nend_of_line = cr\n[*.md]\nend_of_line = unset\n",
+        )
+        .unwrap();
+
+        let mut config = EditorConfig::new();
+        assert_eq!(
+            config.end_of_line(&dir.join("a.rs")).unwrap(),
+            Some(Eol::Lf)
+        );
+        assert_eq!(
+            config.end_of_line(&sub.join("a.bat")).unwrap(),
+            Some(Eol::Crlf)
+        );
+        assert_eq!(
+            config.end_of_line(&sub.join("a.txt")).unwrap(),
+            Some(Eol::Cr)
+        );
+        assert_eq!(config.end_of_line(&sub.join("a.md")).unwrap(), None);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+}
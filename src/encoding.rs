@@ -0,0 +1,268 @@
+//! Character encodings, so that line endings can be converted on code units instead of bytes.
+
+use std::io::{self, Write};
+
+/// Encoding of a file. Line endings are converted on its code units.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    /// Also used for ASCII and other single byte encodings.
+    #[default]
+    Utf8,
+    Utf16Le,
+    Utf16Be,
+    Utf32Le,
+    Utf32Be,
+}
+
+impl Encoding {
+    /// Detect the encoding of content from its byte order mark, or from the pattern of zero
+    /// bytes in its first block if there is none.
+    pub fn detect(block: &[u8]) -> Option<Self> {
+        Self::from_bom(block).or_else(|| Self::guess(block))
+    }
+
+    /// Detect the encoding from the byte order mark at the start of content.
+    pub fn from_bom(block: &[u8]) -> Option<Self> {
+        // UTF-32LE must be checked before UTF-16LE, as their marks share a prefix.
+        [
+            Self::Utf8,
+            Self::Utf32Le,
+            Self::Utf32Be,
+            Self::Utf16Le,
+            Self::Utf16Be,
+        ]
+        .into_iter()
+        .find(|e| block.starts_with(e.bom()))
+    }
+
+    /// Guess a UTF-16 or UTF-32 encoding from zero bytes, which are common in the high bytes of
+    /// their code units for mostly ASCII text.
+    fn guess(block: &[u8]) -> Option<Self> {
+        let units = block.len() / 4;
+        if units == 0 {
+            return None;
+        }
+        let mut zeros = [0; 4];
+        for chunk in block.chunks_exact(4) {
+            for (count, &byte) in zeros.iter_mut().zip(chunk) {
+                *count += usize::from(byte == 0);
+            }
+        }
+        // Zero byte counts are compared to the number of byte positions they were counted at.
+        let mostly = |count: usize, slots: usize| count * 10 >= slots * 9;
+        let often = |count: usize, slots: usize| count * 2 >= slots;
+        let rarely = |count: usize, slots: usize| count * 10 <= slots;
+        let [z0, z1, z2, z3] = zeros;
+        if mostly(z2 + z3, 2 * units) && often(z1, units) && rarely(z0, units) {
+            Some(Self::Utf32Le)
+        } else if mostly(z0 + z1, 2 * units) && often(z2, units) && rarely(z3, units) {
+            Some(Self::Utf32Be)
+        } else if often(z1 + z3, 2 * units) && rarely(z0 + z2, 2 * units) {
+            Some(Self::Utf16Le)
+        } else if often(z0 + z2, 2 * units) && rarely(z1 + z3, 2 * units) {
+            Some(Self::Utf16Be)
+        } else {
+            None
+        }
+    }
+
+    /// Byte order mark of the encoding.
+    pub fn bom(&self) -> &'static [u8] {
+        match self {
+            Encoding::Utf8 => &[0xEF, 0xBB, 0xBF],
+            Encoding::Utf16Le => &[0xFF, 0xFE],
+            Encoding::Utf16Be => &[0xFE, 0xFF],
+            Encoding::Utf32Le => &[0xFF, 0xFE, 0x00, 0x00],
+            Encoding::Utf32Be => &[0x00, 0x00, 0xFE, 0xFF],
+        }
+    }
+
+    /// Size of a code unit in bytes.
+    pub fn unit_len(&self) -> usize {
+        match self {
+            Encoding::Utf8 => 1,
+            Encoding::Utf16Le | Encoding::Utf16Be => 2,
+            Encoding::Utf32Le | Encoding::Utf32Be => 4,
+        }
+    }
+
+    /// Whether code units are wider than a byte.
+    pub fn is_wide(&self) -> bool {
+        self.unit_len() > 1
+    }
+
+    /// Decode a code unit from exactly [`Self::unit_len`] bytes.
+    fn decode(&self, bytes: &[u8]) -> u32 {
+        match self {
+            Encoding::Utf8 => bytes[0].into(),
+            Encoding::Utf16Le => u16::from_le_bytes([bytes[0], bytes[1]]).into(),
+            Encoding::Utf16Be => u16::from_be_bytes([bytes[0], bytes[1]]).into(),
+            Encoding::Utf32Le => u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+            Encoding::Utf32Be => u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]),
+        }
+    }
+
+    /// Write a code unit.
+    pub fn write_unit(&self, writer: &mut impl Write, unit: u32) -> io::Result<()> {
+        match self {
+            Encoding::Utf8 => writer.write_all(&[unit as u8]),
+            Encoding::Utf16Le => writer.write_all(&(unit as u16).to_le_bytes()),
+            Encoding::Utf16Be => writer.write_all(&(unit as u16).to_be_bytes()),
+            Encoding::Utf32Le => writer.write_all(&unit.to_le_bytes()),
+            Encoding::Utf32Be => writer.write_all(&unit.to_be_bytes()),
+        }
+    }
+
+    /// Write bytes as code units, e.g. an ASCII line ending sequence.
+    pub fn write_ascii(&self, writer: &mut impl Write, bytes: &[u8]) -> io::Result<()> {
+        match self {
+            Encoding::Utf8 => writer.write_all(bytes),
+            _ => bytes
+                .iter()
+                .try_for_each(|&b| self.write_unit(writer, b.into())),
+        }
+    }
+
+    /// Decode code units into text for display, replacing invalid sequences.
+    pub fn decode_lossy(&self, units: &[u32]) -> String {
+        match self {
+            Encoding::Utf8 => {
+                String::from_utf8_lossy(&units.iter().map(|&u| u as u8).collect::<Vec<_>>())
+                    .into_owned()
+            },
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                char::decode_utf16(units.iter().map(|&u| u as u16))
+                    .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                    .collect()
+            },
+            Encoding::Utf32Le | Encoding::Utf32Be => units
+                .iter()
+                .map(|&u| char::from_u32(u).unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect(),
+        }
+    }
+}
+
+impl std::str::FromStr for Encoding {
+    type Err = crate::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().replace('-', "").as_str() {
+            "utf8" => Ok(Encoding::Utf8),
+            "utf16le" => Ok(Encoding::Utf16Le),
+            "utf16be" => Ok(Encoding::Utf16Be),
+            "utf32le" => Ok(Encoding::Utf32Le),
+            "utf32be" => Ok(Encoding::Utf32Be),
+            _ => Err(crate::ParseError("encoding")),
+        }
+    }
+}
+
+impl std::fmt::Display for Encoding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Encoding::Utf8 => "UTF-8",
+            Encoding::Utf16Le => "UTF-16LE",
+            Encoding::Utf16Be => "UTF-16BE",
+            Encoding::Utf32Le => "UTF-32LE",
+            Encoding::Utf32Be => "UTF-32BE",
+        })
+    }
+}
+
+/// Iterator over the code units of an encoded byte stream.
+/// Bytes of an incomplete code unit at the end of the stream are kept in `remainder`.
+pub struct Units<B: Iterator<Item = u8>> {
+    bytes: B,
+    encoding: Encoding,
+    pub remainder: Vec<u8>,
+}
+
+impl<B: Iterator<Item = u8>> Units<B> {
+    pub fn new(bytes: B, encoding: Encoding) -> Self {
+        Self {
+            bytes,
+            encoding,
+            remainder: Vec::new(),
+        }
+    }
+}
+
+impl<B: Iterator<Item = u8>> Iterator for Units<B> {
+    type Item = u32;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut buf = [0; 4];
+        let len = self.encoding.unit_len();
+        for i in 0..len {
+            match self.bytes.next() {
+                Some(byte) => buf[i] = byte,
+                None => {
+                    self.remainder.extend_from_slice(&buf[..i]);
+                    return None;
+                },
+            }
+        }
+        Some(self.encoding.decode(&buf[..len]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(text: &str, encoding: Encoding) -> Vec<u8> {
+        let mut out = Vec::new();
+        match encoding {
+            Encoding::Utf8 => out.extend_from_slice(text.as_bytes()),
+            Encoding::Utf16Le | Encoding::Utf16Be => {
+                for unit in text.encode_utf16() {
+                    encoding.write_unit(&mut out, unit.into()).unwrap();
+                }
+            },
+            Encoding::Utf32Le | Encoding::Utf32Be => {
+                for c in text.chars() {
+                    encoding.write_unit(&mut out, c.into()).unwrap();
+                }
+            },
+        }
+        out
+    }
+
+    #[test]
+    fn detect() {
+        for encoding in [
+            Encoding::Utf8,
+            Encoding::Utf16Le,
+            Encoding::Utf16Be,
+            Encoding::Utf32Le,
+            Encoding::Utf32Be,
+        ] {
+            let mut bytes = encoding.bom().to_vec();
+            bytes.extend(encode("Windows Registry Editor\r\n", encoding));
+            assert_eq!(Encoding::detect(&bytes), Some(encoding));
+            if encoding.is_wide() {
+                let bytes = encode("[HKEY_CURRENT_USER]\r\nä=ö\r\n", encoding);
+                assert_eq!(Encoding::detect(&bytes), Some(encoding));
+            }
+        }
+        assert_eq!(Encoding::detect(b"plain text\r\n"), None);
+        assert_eq!(
+            Encoding::detect(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR\0\0\0\x10"),
+            None
+        );
+    }
+
+    #[test]
+    fn units() {
+        let bytes = encode("a\r\n", Encoding::Utf16Be);
+        let mut units = Units::new(bytes.into_iter().chain([0xAB]), Encoding::Utf16Be);
+        assert_eq!(units.by_ref().collect::<Vec<_>>(), vec![0x61, 0x0D, 0x0A]);
+        assert_eq!(units.remainder, vec![0xAB]);
+        assert_eq!(
+            Encoding::Utf16Le
+                .decode_lossy(&"ää😀".encode_utf16().map(u32::from).collect::<Vec<_>>()),
+            "ää😀"
+        );
+    }
+}
@@ -0,0 +1,154 @@
+//! Errors of processing files, which are collected and reported at the end of a run.
+
+use std::path::{Path, PathBuf};
+
+/// Operation that failed on a file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Operation {
+    Walk,
+    Read,
+    Resolve,
+    Convert,
+    Write,
+    Replace,
+}
+
+impl std::fmt::Display for Operation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Operation::Walk => "walking",
+            Operation::Read => "reading",
+            Operation::Resolve => "resolving the line ending of",
+            Operation::Convert => "converting",
+            Operation::Write => "writing the output of",
+            Operation::Replace => "replacing",
+        })
+    }
+}
+
+/// Error of processing a file.
+#[derive(Debug)]
+pub enum Error {
+    /// An operation failed on a file.
+    File {
+        path: PathBuf,
+        operation: Operation,
+        source: newl::Error,
+    },
+    /// Walking a directory failed, the error includes the path if known.
+    Walk(ignore::Error),
+}
+
+impl Error {
+    /// Whether the content of the file contains a line break rejected by its policy, or rejected
+    /// lone `CR`s.
+    pub fn is_rejected(&self) -> bool {
+        matches!(self, Error::File {
+            source: newl::Error::Rejected(_) | newl::Error::LoneCr { .. },
+            ..
+        })
+    }
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::File {
+                path,
+                operation,
+                source,
+            } => write!(f, "Error {operation} {}: {source}", path.display()),
+            Error::Walk(e) => write!(f, "Error walking: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::File { source, .. } => Some(source),
+            Error::Walk(e) => Some(e),
+        }
+    }
+}
+
+/// Attach the path and the operation to the error of an operation on a file.
+pub trait Context<T> {
+    fn on_file(self, path: &Path, operation: Operation) -> Result<T, Error>;
+}
+
+impl<T, E: Into<newl::Error>> Context<T> for Result<T, E> {
+    fn on_file(self, path: &Path, operation: Operation) -> Result<T, Error> {
+        self.map_err(|e| Error::File {
+            path: path.to_path_buf(),
+            operation,
+            source: e.into(),
+        })
+    }
+}
+
+/// Collects the errors of a run, or exits at the first one if failing fast.
+#[derive(Debug)]
+pub struct Errors {
+    fail_fast: bool,
+    errors: Vec<Error>,
+}
+
+impl Errors {
+    pub fn new(fail_fast: bool) -> Self {
+        Self {
+            fail_fast,
+            errors: Vec::new(),
+        }
+    }
+
+    pub fn push(&mut self, error: Error) {
+        if self.fail_fast {
+            crate::exit_with_error(error);
+        }
+        self.errors.push(error);
+    }
+
+    /// Print the collected errors and exit with a failure status, if there were any.
+    pub fn report(self) {
+        if self.errors.is_empty() {
+            return;
+        }
+        for error in &self.errors {
+            eprintln!("{error}");
+        }
+        match self.errors.len() {
+            1 => crate::exit_with_error("1 error"),
+            n => crate::exit_with_error(format_args!("{n} errors")),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn display() {
+        let error = Err::<(), _>(std::io::Error::other("denied"))
+            .on_file(Path::new("a.txt"), Operation::Read)
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Error reading a.txt: denied");
+        assert!(!error.is_rejected());
+        let error = Err::<(), _>(newl::Error::Rejected(newl::Separator::Ls))
+            .on_file(Path::new("b.txt"), Operation::Convert)
+            .unwrap_err();
+        assert_eq!(
+            error.to_string(),
+            "Error converting b.txt: Contains a rejected LS line break"
+        );
+        assert!(error.is_rejected());
+        let error = Err::<(), _>(newl::Error::LoneCr {
+            offsets: vec![3],
+            count: 1,
+        })
+        .on_file(Path::new("c.txt"), Operation::Convert)
+        .unwrap_err();
+        assert!(error.is_rejected());
+    }
+}
@@ -0,0 +1,459 @@
+//! Resolution of the `text` and `eol` attributes from `.gitattributes` files.
+//!
+//! Follows the precedence and matching rules of `gitattributes(5)`: attribute files closer to a
+//! path take precedence, later lines take precedence over earlier ones, and `$GIT_DIR/info/
+//! attributes` takes precedence over everything. Macro attributes, including the built-in
+//! `binary` macro, are expanded when set. The global `core.attributesFile` is not read.
+
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::{fs, io};
+
+use newl::Eol;
+
+const FILENAME: &str = ".gitattributes";
+
+/// What the attributes of a path say about converting it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Target {
+    /// The path is not text (`-text`), so it must not be converted.
+    Skip,
+    /// The path should be converted to the sequence of its `eol` attribute.
+    Eol(Eol),
+    /// Neither attribute decides the sequence for the path.
+    Unspecified,
+}
+
+/// State of an attribute for a path.
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum State {
+    /// `attr`
+    Set,
+    /// `-attr`
+    Unset,
+    /// `attr=value`
+    Value(String),
+    /// `!attr`, or no matching assignment.
+    Unspecified,
+}
+
+type Assignments = Vec<(String, State)>;
+
+/// Resolves attributes for files, caching parsed attribute files per directory.
+#[derive(Debug, Default)]
+pub struct GitAttributes {
+    cache: HashMap<PathBuf, Option<Rc<AttrFile>>>,
+}
+
+impl GitAttributes {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Resolve the conversion target of a file from its `text` and `eol` attributes.
+    pub fn target(&mut self, path: &Path) -> io::Result<Target> {
+        let path = std::path::absolute(path)?;
+        let Some(dir) = path.parent() else {
+            return Ok(Target::Unspecified);
+        };
+        let root = dir
+            .ancestors()
+            .find(|d| d.join(".git").exists())
+            .unwrap_or_else(|| dir.ancestors().last().unwrap_or(dir));
+
+        // Attribute files in order of decreasing precedence, with their base directories.
+        let mut files = Vec::new();
+        if let Some(file) = self.load(&root.join(".git").join("info").join("attributes"))? {
+            files.push((root, file));
+        }
+        for dir in dir.ancestors() {
+            if let Some(file) = self.load(&dir.join(FILENAME))? {
+                files.push((dir, file));
+            }
+            if dir == root {
+                break;
+            }
+        }
+
+        // Macros can only be defined at the top level.
+        let mut macros = HashMap::from([("binary".to_string(), vec![
+            ("diff".to_string(), State::Unset),
+            ("merge".to_string(), State::Unset),
+            ("text".to_string(), State::Unset),
+        ])]);
+        for (dir, file) in files.iter().rev() {
+            if *dir == root {
+                macros.extend(file.macros.iter().cloned());
+            }
+        }
+
+        let mut attrs = HashMap::new();
+        for (dir, file) in &files {
+            let Some(relative) = path.strip_prefix(dir).ok().and_then(to_slash) else {
+                continue;
+            };
+            for rule in file.rules.iter().rev() {
+                if rule.pattern.is_match(&relative) {
+                    for (name, state) in rule.assignments.iter().rev() {
+                        assign(&mut attrs, &macros, name, state);
+                    }
+                }
+            }
+        }
+
+        let eol = match attrs.get("eol") {
+            Some(State::Value(value)) => value.parse().ok(),
+            _ => None,
+        };
+        Ok(match (attrs.get("text"), eol) {
+            (Some(State::Unset), _) => Target::Skip,
+            (_, Some(eol)) => Target::Eol(eol),
+            _ => Target::Unspecified,
+        })
+    }
+
+    fn load(&mut self, path: &Path) -> io::Result<Option<Rc<AttrFile>>> {
+        if let Some(file) = self.cache.get(path) {
+            return Ok(file.clone());
+        }
+        let file = match fs::read_to_string(path) {
+            Ok(text) => Some(Rc::new(AttrFile::parse(&text))),
+            Err(e)
+                if matches!(
+                    e.kind(),
+                    io::ErrorKind::NotFound
+                        | io::ErrorKind::IsADirectory
+                        | io::ErrorKind::NotADirectory
+                ) =>
+            {
+                None
+            },
+            Err(e) => return Err(e),
+        };
+        self.cache.insert(path.to_path_buf(), file.clone());
+        Ok(file)
+    }
+}
+
+/// Assign an attribute unless a more specific assignment was already made, expanding macros.
+fn assign(
+    attrs: &mut HashMap<String, State>,
+    macros: &HashMap<String, Assignments>,
+    name: &str,
+    state: &State,
+) {
+    if attrs.contains_key(name) {
+        return;
+    }
+    attrs.insert(name.to_string(), state.clone());
+    if *state == State::Set
+        && let Some(expansion) = macros.get(name)
+    {
+        for (name, state) in expansion.iter().rev() {
+            assign(attrs, macros, name, state);
+        }
+    }
+}
+
+/// Join path components with `/`, failing on non-UTF-8 components.
+fn to_slash(path: &Path) -> Option<String> {
+    let parts = path
+        .components()
+        .map(|c| c.as_os_str().to_str())
+        .collect::<Option<Vec<_>>>()?;
+    Some(parts.join("/"))
+}
+
+#[derive(Debug, Default)]
+struct AttrFile {
+    rules: Vec<Rule>,
+    macros: Vec<(String, Assignments)>,
+}
+
+#[derive(Debug)]
+struct Rule {
+    pattern: Pattern,
+    assignments: Assignments,
+}
+
+impl AttrFile {
+    fn parse(text: &str) -> Self {
+        let mut file = Self::default();
+        for line in text.lines() {
+            let line = line.trim_start();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (pattern, rest): (Cow<str>, &str) = match line.strip_prefix('"') {
+                Some(quoted) => match unquote(quoted) {
+                    Some(split) => split,
+                    None => continue,
+                },
+                None => {
+                    let (pattern, rest) = line.split_once([' ', '\t']).unwrap_or((line, ""));
+                    (pattern.into(), rest)
+                },
+            };
+            let pattern = pattern.as_ref();
+            let assignments = rest
+                .split_whitespace()
+                .map(|attr| match attr.as_bytes()[0] {
+                    b'-' => (attr[1..].to_string(), State::Unset),
+                    b'!' => (attr[1..].to_string(), State::Unspecified),
+                    _ => match attr.split_once('=') {
+                        Some((name, value)) => (name.to_string(), State::Value(value.to_string())),
+                        None => (attr.to_string(), State::Set),
+                    },
+                })
+                .filter(|(name, _)| !name.is_empty())
+                .collect();
+
+            if let Some(name) = pattern.strip_prefix("[attr]") {
+                file.macros.push((name.to_string(), assignments));
+            } else if pattern.starts_with('!') {
+                // Negative patterns are forbidden in attribute files, git ignores them.
+            } else {
+                file.rules.push(Rule {
+                    pattern: Pattern::new(pattern),
+                    assignments,
+                });
+            }
+        }
+        file
+    }
+}
+
+/// Split a C-style quoted pattern (without the opening quote) from the rest of the line.
+fn unquote(s: &str) -> Option<(Cow<'_, str>, &str)> {
+    let mut pattern = String::new();
+    let mut chars = s.char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '"' => return Some((pattern.into(), &s[i + 1..])),
+            '\\' => match chars.next()?.1 {
+                'n' => pattern.push('\n'),
+                't' => pattern.push('\t'),
+                c => pattern.push(c),
+            },
+            c => pattern.push(c),
+        }
+    }
+    None
+}
+
+/// Gitignore-style pattern, as used by attribute files.
+#[derive(Debug)]
+struct Pattern {
+    tokens: Vec<Token>,
+    /// Patterns without a `/` match the basename at any directory level.
+    basename: bool,
+}
+
+#[derive(Debug)]
+enum Token {
+    Char(char),
+    /// `?`
+    Any,
+    /// `*`
+    Star,
+    /// `**` after a `/` at the end of a pattern.
+    Globstar,
+    /// `**/` at the start of a pattern or after a `/`, matching zero or more directories.
+    Dirs,
+    /// `[...]`
+    Class {
+        negated: bool,
+        ranges: Vec<(char, char)>,
+    },
+}
+
+impl Pattern {
+    fn new(pattern: &str) -> Self {
+        let basename = !pattern.contains('/');
+        let pattern = pattern.strip_prefix('/').unwrap_or(pattern);
+        let chars = pattern.chars().collect::<Vec<_>>();
+        let mut tokens = Vec::new();
+        let mut pos = 0;
+        while let Some(&c) = chars.get(pos) {
+            let segment_start = pos == 0 || chars[pos - 1] == '/';
+            match c {
+                '\\' if pos + 1 < chars.len() => {
+                    tokens.push(Token::Char(chars[pos + 1]));
+                    pos += 2;
+                },
+                '*' if chars.get(pos + 1) == Some(&'*') && segment_start => {
+                    match chars.get(pos + 2) {
+                        Some('/') => {
+                            tokens.push(Token::Dirs);
+                            pos += 3;
+                        },
+                        None => {
+                            tokens.push(Token::Globstar);
+                            pos += 2;
+                        },
+                        Some(_) => {
+                            tokens.push(Token::Star);
+                            pos += 2;
+                        },
+                    }
+                },
+                '*' => {
+                    tokens.push(Token::Star);
+                    while chars.get(pos) == Some(&'*') {
+                        pos += 1;
+                    }
+                },
+                '?' => {
+                    tokens.push(Token::Any);
+                    pos += 1;
+                },
+                '[' => match parse_class(&chars, pos) {
+                    Some((token, end)) => {
+                        tokens.push(token);
+                        pos = end;
+                    },
+                    None => {
+                        tokens.push(Token::Char('['));
+                        pos += 1;
+                    },
+                },
+                c => {
+                    tokens.push(Token::Char(c));
+                    pos += 1;
+                },
+            }
+        }
+        Self { tokens, basename }
+    }
+
+    fn is_match(&self, path: &str) -> bool {
+        let path = path.chars().collect::<Vec<_>>();
+        let text = match path.iter().rposition(|&c| c == '/') {
+            Some(i) if self.basename => &path[i + 1..],
+            _ => &path[..],
+        };
+        matches(&self.tokens, text)
+    }
+}
+
+/// Parse a bracket expression starting at `[`, returning the token and the position after `]`.
+fn parse_class(chars: &[char], start: usize) -> Option<(Token, usize)> {
+    let mut pos = start + 1;
+    let negated = matches!(chars.get(pos), Some('!' | '^'));
+    if negated {
+        pos += 1;
+    }
+    let mut ranges = Vec::new();
+    let mut first = true;
+    loop {
+        let c = match *chars.get(pos)? {
+            ']' if !first => return Some((Token::Class { negated, ranges }, pos + 1)),
+            '\\' => {
+                pos += 1;
+                *chars.get(pos)?
+            },
+            c => c,
+        };
+        if chars.get(pos + 1) == Some(&'-') && chars.get(pos + 2).is_some_and(|&e| e != ']') {
+            ranges.push((c, chars[pos + 2]));
+            pos += 3;
+        } else {
+            ranges.push((c, c));
+            pos += 1;
+        }
+        first = false;
+    }
+}
+
+fn matches(tokens: &[Token], text: &[char]) -> bool {
+    let Some((token, rest)) = tokens.split_first() else {
+        return text.is_empty();
+    };
+    match token {
+        Token::Char(c) => text.first() == Some(c) && matches(rest, &text[1..]),
+        Token::Any => text.first().is_some_and(|&c| c != '/') && matches(rest, &text[1..]),
+        Token::Star => (0..=text.len())
+            .take_while(|&i| i == 0 || text[i - 1] != '/')
+            .any(|i| matches(rest, &text[i..])),
+        Token::Globstar => !text.is_empty(),
+        Token::Dirs => {
+            matches(rest, text)
+                || (0..text.len())
+                    .filter(|&i| text[i] == '/')
+                    .any(|i| matches(rest, &text[i + 1..]))
+        },
+        Token::Class { negated, ranges } => {
+            text.first().is_some_and(|&c| {
+                c != '/' && ranges.iter().any(|&(lo, hi)| lo <= c && c <= hi) != *negated
+            }) && matches(rest, &text[1..])
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn is_match(pattern: &str, path: &str) -> bool {
+        Pattern::new(pattern).is_match(path)
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(is_match("*", "a/b.txt"));
+        assert!(is_match("*.ps1", "scripts/run.ps1"));
+        assert!(!is_match("*.ps1", "run.ps1.bak"));
+        assert!(is_match("docs/*.md", "docs/a.md"));
+        assert!(!is_match("docs/*.md", "docs/sub/a.md"));
+        assert!(!is_match("docs/*.md", "src/docs/a.md"));
+        assert!(is_match("/docs/*.md", "docs/a.md"));
+        assert!(is_match("**/vendor/*.c", "vendor/a.c"));
+        assert!(is_match("**/vendor/*.c", "x/y/vendor/a.c"));
+        assert!(is_match("vendor/**", "vendor/x/y.c"));
+        assert!(!is_match("vendor/**", "vendor"));
+        assert!(is_match("a/**/b", "a/b"));
+        assert!(is_match("a/**/b", "a/x/y/b"));
+        assert!(is_match("file[0-9].[ch]", "file1.h"));
+        assert!(!is_match("file[!0-9].c", "file1.c"));
+    }
+
+    fn resolve(attributes: &str, path: &str) -> Target {
+        let dir = std::env::temp_dir().join(format!("newl-gitattributes-{}", std::process::id()));
+        fs::create_dir_all(dir.join(".git")).unwrap();
+        fs::write(dir.join(FILENAME), attributes).unwrap();
+        let target = GitAttributes::new().target(&dir.join(path)).unwrap();
+        fs::remove_dir_all(&dir).unwrap();
+        target
+    }
+
+    #[test]
+    fn targets() {
+        let attributes = "* text=auto eol=lf\n*.ps1 eol=crlf\n*.png binary\n*.bin -text\n";
+        assert_eq!(resolve(attributes, "a.rs"), Target::Eol(Eol::Lf));
+        assert_eq!(resolve(attributes, "sub/a.ps1"), Target::Eol(Eol::Crlf));
+        assert_eq!(resolve(attributes, "a.png"), Target::Skip);
+        assert_eq!(resolve(attributes, "a.bin"), Target::Skip);
+
+        assert_eq!(
+            resolve("* eol=crlf\n*.txt !eol\n", "a.txt"),
+            Target::Unspecified
+        );
+        assert_eq!(resolve("*.txt text\n", "a.txt"), Target::Unspecified);
+        assert_eq!(
+            resolve("*.png binary\n*.png -binary\n", "a.png"),
+            Target::Unspecified
+        );
+        assert_eq!(
+            resolve("*.png -binary\n*.png binary\n", "a.png"),
+            Target::Skip
+        );
+        assert_eq!(resolve("*.dat binary text\n", "a.dat"), Target::Unspecified);
+        assert_eq!(
+            resolve("[attr]crlf-text text eol=crlf\n*.bat crlf-text\n", "a.bat"),
+            Target::Eol(Eol::Crlf)
+        );
+        assert_eq!(resolve("!*.txt -text\n", "a.txt"), Target::Unspecified);
+    }
+}
@@ -0,0 +1,179 @@
+//! Conversion of indentation between tabs and spaces, one code unit at a time.
+
+use crate::Token;
+use crate::encoding::Encoding;
+
+const SPACE: u32 = b' ' as u32;
+const TAB: u32 = b'\t' as u32;
+
+/// Character used for indentation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Indent {
+    Tabs,
+    Spaces,
+}
+
+impl std::str::FromStr for Indent {
+    type Err = crate::ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "tabs" => Ok(Indent::Tabs),
+            "spaces" => Ok(Indent::Spaces),
+            _ => Err(crate::ParseError("indentation")),
+        }
+    }
+}
+
+impl std::fmt::Display for Indent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Indent::Tabs => "tabs",
+            Indent::Spaces => "spaces",
+        })
+    }
+}
+
+/// Converts the leading indentation of lines, and optionally expands all tabs to spaces.
+/// Tokens are fed in with [`Reindent::push`], which holds back indentation until its end.
+pub struct Reindent {
+    indent: Indent,
+    tab_width: usize,
+    /// Whether tabs after the indentation are expanded too, when indenting with spaces.
+    all_tabs: bool,
+    encoding: Encoding,
+    /// Indentation of the current line, while it has not ended.
+    leading: Option<Vec<u32>>,
+    column: usize,
+    line_changed: bool,
+    /// Number of lines changed.
+    pub lines: usize,
+}
+
+impl Reindent {
+    pub fn new(indent: Indent, tab_width: usize, all_tabs: bool, encoding: Encoding) -> Self {
+        Self {
+            indent,
+            tab_width: tab_width.max(1),
+            all_tabs,
+            encoding,
+            leading: Some(Vec::new()),
+            column: 0,
+            line_changed: false,
+            lines: 0,
+        }
+    }
+
+    /// Feed a token, or `None` at the end of content, and append the resulting tokens to `out`.
+    pub fn push(&mut self, token: Option<Token>, out: &mut Vec<Token>) {
+        match (token, &mut self.leading) {
+            (Some(Token::Unit(unit @ (SPACE | TAB))), Some(leading)) => leading.push(unit),
+            (Some(Token::Unit(unit)), _) => {
+                self.end_indentation(out);
+                if unit == TAB && self.all_tabs && self.indent == Indent::Spaces {
+                    let width = self.tab_width - self.column % self.tab_width;
+                    out.extend(std::iter::repeat_n(Token::Unit(SPACE), width));
+                    self.column += width;
+                    self.line_changed = true;
+                } else {
+                    out.push(Token::Unit(unit));
+                    if unit == TAB {
+                        self.column += self.tab_width - self.column % self.tab_width;
+                    } else if !self.is_continuation(unit) {
+                        self.column += 1;
+                    }
+                }
+            },
+            _ => {
+                self.end_indentation(out);
+                out.extend(token);
+                if self.line_changed {
+                    self.lines += 1;
+                }
+                self.leading = Some(Vec::new());
+                self.column = 0;
+                self.line_changed = false;
+            },
+        }
+    }
+
+    /// Write out the indentation of the current line converted, if it has not been yet.
+    fn end_indentation(&mut self, out: &mut Vec<Token>) {
+        let Some(leading) = self.leading.take() else {
+            return;
+        };
+        let width = leading.iter().fold(0, |width, &unit| match unit {
+            TAB => width + self.tab_width - width % self.tab_width,
+            _ => width + 1,
+        });
+        let indentation = match self.indent {
+            Indent::Spaces => vec![SPACE; width],
+            Indent::Tabs => {
+                let mut units = vec![TAB; width / self.tab_width];
+                units.resize(units.len() + width % self.tab_width, SPACE);
+                units
+            },
+        };
+        self.line_changed |= indentation != leading;
+        out.extend(indentation.into_iter().map(Token::Unit));
+        self.column = width;
+    }
+
+    /// Whether a code unit continues a character instead of starting a new column.
+    fn is_continuation(&self, unit: u32) -> bool {
+        match self.encoding {
+            Encoding::Utf8 => (0x80..0xC0).contains(&unit),
+            Encoding::Utf16Le | Encoding::Utf16Be => (0xDC00..0xE000).contains(&unit),
+            Encoding::Utf32Le | Encoding::Utf32Be => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Eol;
+
+    fn reindent(indent: Indent, all_tabs: bool, input: &str) -> (String, usize) {
+        let mut reindent = Reindent::new(indent, 4, all_tabs, Encoding::Utf8);
+        let mut out = Vec::new();
+        let tokens = input.bytes().map(|b| match b {
+            b'\n' => Token::Eol(Eol::Lf),
+            b => Token::Unit(b.into()),
+        });
+        for token in tokens.map(Some).chain([None]) {
+            reindent.push(token, &mut out);
+        }
+        let bytes = out
+            .into_iter()
+            .map(|token| match token {
+                Token::Unit(unit) => unit as u8,
+                _ => b'\n',
+            })
+            .collect();
+        (String::from_utf8(bytes).unwrap(), reindent.lines)
+    }
+
+    #[test]
+    fn spaces() {
+        assert_eq!(reindent(Indent::Spaces, false, "a\n"), ("a\n".into(), 0));
+        assert_eq!(
+            reindent(Indent::Spaces, false, "\ta\tb\n  \tc\n    d"),
+            ("    a\tb\n    c\n    d".into(), 2)
+        );
+        assert_eq!(
+            reindent(Indent::Spaces, true, "\tä\tb\n ab\tc\td"),
+            ("    ä   b\n ab c   d".into(), 2)
+        );
+        assert_eq!(reindent(Indent::Spaces, false, " \t"), ("    ".into(), 1));
+    }
+
+    #[test]
+    fn tabs() {
+        assert_eq!(
+            reindent(Indent::Tabs, false, "      a  \n\tb\n   \tc"),
+            ("\t  a  \n\tb\n\tc".into(), 2)
+        );
+        assert_eq!(reindent(Indent::Tabs, true, "a    b"), ("a    b".into(), 0));
+    }
+}
@@ -0,0 +1,1735 @@
+//! Conversion of line endings in text, optionally together with other whitespace cleanups.
+//!
+//! Content is converted in a single streaming pass, on the code units of its encoding. Conversions
+//! of only line endings in single byte content copy the bytes between them in large chunks.
+//!
+//! ```
+//! use newl::{Conversion, Eol};
+//!
+//! let (output, _) = newl::convert_slice(b"a\r\nb\r", Conversion::new(Eol::Lf)).unwrap();
+//! assert_eq!(output, b"a\nb\n");
+//! ```
+
+use std::collections::VecDeque;
+use std::io::{self, BufRead, BufReader, BufWriter, Read, Write};
+
+use chunked::Chunked;
+use classify::{Classifier, Histogram};
+pub use encoding::Encoding;
+use encoding::Units;
+pub use indent::Indent;
+use indent::Reindent;
+
+mod chunked;
+mod classify;
+mod encoding;
+mod indent;
+
+const CR: u8 = 0x0D;
+const LF: u8 = 0x0A;
+const FF: u8 = 0x0C;
+
+/// Number of bytes inspected from the start of content to detect its encoding, or to decide
+/// whether it is binary.
+pub const SNIFF_LEN: usize = 8000;
+
+/// Size of the buffers that content is read into and written from by [`convert`].
+const CHUNK_LEN: usize = 64 * 1024;
+
+/// Number of unchanged lines shown around changes by [`diff`].
+const DIFF_CONTEXT: usize = 3;
+
+pub const DEFAULT_TAB_WIDTH: usize = 4;
+
+/// Number of lone `CR` offsets kept for [`Error::LoneCr`].
+const LONE_CR_OFFSETS: usize = 10;
+
+/// Error of a conversion.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// The content contains a line break that is rejected by its policy.
+    Rejected(Separator),
+    /// The content contains lone `CR`s, which are rejected by [`LoneCr::Error`]. Holds the byte
+    /// offsets of the first ones, and how many there are.
+    LoneCr {
+        offsets: Vec<u64>,
+        count: usize,
+    },
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Error::Io(e) => write!(f, "{e}"),
+            Error::Rejected(separator) => write!(f, "Contains a rejected {separator} line break"),
+            Error::LoneCr { offsets, count } => {
+                let list = offsets
+                    .iter()
+                    .map(u64::to_string)
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                match count {
+                    1 => write!(f, "Contains a lone CR at byte offset {list}")?,
+                    _ => write!(f, "Contains {count} lone CRs at byte offsets {list}")?,
+                }
+                if *count > offsets.len() {
+                    write!(f, " and {} more", count - offsets.len())?;
+                }
+                std::fmt::Result::Ok(())
+            },
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(e) => Some(e),
+            Error::Rejected(_) | Error::LoneCr { .. } => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
+
+pub type Result<T, E = Error> = std::result::Result<T, E>;
+
+/// Byte offsets of the lone `CR`s found in content, for rejecting them.
+#[derive(Debug, Default)]
+struct LoneCrs {
+    offsets: Vec<u64>,
+    count: usize,
+}
+
+impl LoneCrs {
+    fn push(&mut self, offset: u64) {
+        if self.offsets.len() < LONE_CR_OFFSETS {
+            self.offsets.push(offset);
+        }
+        self.count += 1;
+    }
+
+    /// An error if any lone `CR`s were found.
+    fn check(self) -> Result<()> {
+        match self.count {
+            0 => Ok(()),
+            count => Err(Error::LoneCr {
+                offsets: self.offsets,
+                count,
+            }),
+        }
+    }
+}
+
+/// Error for an unknown option value, e.g. when parsing an [`Eol`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseError(&'static str);
+
+impl std::fmt::Display for ParseError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Unknown {}", self.0)
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Convert content from `reader` to `writer`, detecting its encoding unless it is set.
+/// Returns the changes made besides line endings.
+pub fn convert(reader: impl Read, writer: impl Write, conversion: Conversion) -> Result<Changes> {
+    let mut reader = BufReader::with_capacity(CHUNK_LEN, reader);
+    let conversion = conversion.detect(reader.fill_buf()?);
+    let mut writer = BufWriter::with_capacity(CHUNK_LEN, writer);
+    let changes = if conversion.is_chunked() {
+        conversion.transform_chunks(reader, &mut writer)?
+    } else {
+        let mut bytes = Bytes::new(reader);
+        let changes = conversion.transform(&mut bytes, &mut writer)?;
+        bytes.finish()?;
+        changes
+    };
+    writer.flush()?;
+    Ok(changes)
+}
+
+/// Convert content in memory. Returns the output and the changes made besides line endings.
+pub fn convert_slice(input: &[u8], conversion: Conversion) -> Result<(Vec<u8>, Changes)> {
+    let mut output = Vec::with_capacity(input.len());
+    let changes = convert_slice_to(input, &mut output, conversion)?;
+    Ok((output, changes))
+}
+
+/// Convert content in memory to `writer`, e.g. a memory-mapped file.
+/// Returns the changes made besides line endings.
+pub fn convert_slice_to(
+    input: &[u8],
+    writer: impl Write,
+    conversion: Conversion,
+) -> Result<Changes> {
+    let conversion = conversion.detect(&input[..input.len().min(SNIFF_LEN)]);
+    let mut writer = BufWriter::with_capacity(CHUNK_LEN, writer);
+    let changes = match conversion.is_chunked() {
+        true => conversion.transform_chunks(input, &mut writer)?,
+        false => conversion.transform(input.iter().copied(), &mut writer)?,
+    };
+    writer.flush()?;
+    Ok(changes)
+}
+
+/// Whether converting content certainly leaves it unchanged, which is decided without converting
+/// it for conversions of only line endings, by classifying the line endings.
+/// Returns `false` if the content may change.
+pub fn is_unchanged(content: &[u8], conversion: Conversion) -> bool {
+    let conversion = conversion.detect(&content[..content.len().min(SNIFF_LEN)]);
+    if !conversion.is_chunked() {
+        return false;
+    }
+    let bom = conversion.encoding().bom();
+    let present = content.starts_with(bom);
+    let bom_kept = match conversion.bom {
+        Bom::Add => present,
+        Bom::Strip => !present,
+        Bom::Keep => true,
+    };
+    let text = if present {
+        &content[bom.len()..]
+    } else {
+        content
+    };
+    let lone_cr = conversion.lone_cr;
+    let ends_with_break =
+        text.ends_with(&[LF]) || (text.ends_with(&[CR]) && lone_cr == LoneCr::Convert);
+    let final_newline_kept = match conversion.final_newline {
+        FinalNewline::Ensure => text.is_empty() || ends_with_break,
+        FinalNewline::Strip => !ends_with_break,
+        FinalNewline::Keep => true,
+    };
+    if !bom_kept || !final_newline_kept {
+        return false;
+    }
+    // Searching for any `CR` is faster than classifying, where that is enough.
+    if conversion.eol == Eol::Lf && lone_cr != LoneCr::Keep {
+        return memchr::memchr(CR, text).is_none();
+    }
+    let kept = |histogram: Histogram| match lone_cr {
+        LoneCr::Convert => histogram.only(conversion.eol),
+        LoneCr::Keep => Histogram { cr: 0, ..histogram }.only(conversion.eol),
+        LoneCr::Error => histogram.cr == 0 && histogram.only(conversion.eol),
+    };
+    // Stops at the first chunk with another line ending than the target.
+    let mut classifier = Classifier::default();
+    text.chunks(CHUNK_LEN)
+        .all(|chunk| kept(classifier.push(chunk)))
+        && kept(classifier.finish())
+}
+
+/// Count the line endings in content from `reader`, detecting its encoding unless it is set.
+/// Separators are counted as recognized by `conversion`, regardless of their policies, and lone
+/// `CR`s are always counted.
+pub fn scan(reader: impl Read, conversion: Conversion) -> Result<EolStats> {
+    let mut reader = BufReader::with_capacity(CHUNK_LEN, reader);
+    let conversion = conversion.detect(reader.fill_buf()?);
+    let encoding = conversion.encoding();
+    if !encoding.is_wide() && !conversion.unicode && conversion.form_feed == Policy::Keep {
+        let mut classifier = Classifier::default();
+        let mut stats = EolStats::default();
+        loop {
+            let buf = reader.fill_buf()?;
+            if buf.is_empty() {
+                break;
+            }
+            stats.add(classifier.push(buf));
+            let len = buf.len();
+            reader.consume(len);
+        }
+        stats.add(classifier.finish());
+        return Ok(EolStats { encoding, ..stats });
+    }
+    let mut bytes = Bytes::new(reader);
+    let tokens = Tokens {
+        lone_cr: true,
+        repair: false,
+        ..conversion.tokens(Units::new(&mut bytes, encoding))
+    };
+    let stats = EolStats::scan(tokens);
+    bytes.finish()?;
+    Ok(EolStats { encoding, ..stats })
+}
+
+/// Iterator over the bytes of a reader, which ends at the first error and keeps it.
+struct Bytes<R: BufRead> {
+    bytes: io::Bytes<R>,
+    error: Option<io::Error>,
+}
+
+impl<R: BufRead> Bytes<R> {
+    fn new(reader: R) -> Self {
+        Self {
+            bytes: reader.bytes(),
+            error: None,
+        }
+    }
+
+    /// The error that ended the iteration, if any.
+    fn finish(self) -> io::Result<()> {
+        self.error.map_or(io::Result::Ok(()), Err)
+    }
+}
+
+impl<R: BufRead> Iterator for Bytes<R> {
+    type Item = u8;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self.bytes.next()? {
+            io::Result::Ok(byte) => Some(byte),
+            Err(e) => {
+                self.error = Some(e);
+                None
+            },
+        }
+    }
+}
+
+/// Binary content either contains a NUL byte or has too many non-text control bytes.
+/// UTF-16 and UTF-32 text is not binary, even though it contains NUL bytes.
+pub fn looks_binary(block: &[u8]) -> bool {
+    if Encoding::detect(block).is_some_and(|e| e.is_wide()) {
+        return false;
+    }
+    if block.contains(&0) {
+        return true;
+    }
+    let control = block
+        .iter()
+        .filter(|&&b| (b < 0x20 && !matches!(b, b'\t' | LF | CR | 0x0C | 0x1B | 0x08)) || b == 0x7F)
+        .count();
+    control * 10 > block.len() * 3
+}
+
+/// Print the lines of content whose line endings would change as a unified diff, with `name` as
+/// the file name in its header. Returns whether there were any changes.
+pub fn diff(
+    content: &[u8],
+    name: &str,
+    conversion: Conversion,
+    mut output: impl Write,
+) -> Result<bool> {
+    let conversion = conversion.detect(&content[..content.len().min(SNIFF_LEN)]);
+    let encoding = conversion.encoding();
+    let units = Units::new(content.iter().copied(), encoding).collect::<Vec<_>>();
+    if conversion.lone_cr == LoneCr::Error {
+        let mut lone_crs = LoneCrs::default();
+        for (i, &unit) in units.iter().enumerate() {
+            if unit == CR.into() && units.get(i + 1) != Some(&LF.into()) {
+                lone_crs.push((i * encoding.unit_len()) as u64);
+            }
+        }
+        lone_crs.check()?;
+    }
+    let lines = split_lines(&units, &conversion);
+    let changed = (0..lines.len())
+        .filter_map(|i| match lines[i].1 {
+            Some(ending) => match conversion.ending(ending) {
+                Err(e) => Some(Err(e)),
+                Ok(new) => (new != ending).then_some(Ok(i)),
+            },
+            None => None,
+        })
+        .collect::<Result<Vec<_>>>()?;
+    if changed.is_empty() {
+        return Ok(false);
+    }
+
+    writeln!(output, "--- a/{name}")?;
+    writeln!(output, "+++ b/{name}")?;
+    let mut changed = changed.iter().peekable();
+    while let Some(&first) = changed.next() {
+        let mut last = first;
+        while let Some(&&next) = changed.peek() {
+            if next - last > 2 * DIFF_CONTEXT {
+                break;
+            }
+            last = next;
+            changed.next();
+        }
+        let start = first.saturating_sub(DIFF_CONTEXT);
+        let end = (last + DIFF_CONTEXT + 1).min(lines.len());
+        let len = end - start;
+        writeln!(output, "@@ -{},{len} +{},{len} @@", start + 1, start + 1)?;
+        for &(line, ending) in &lines[start..end] {
+            let visible = |ending: Option<Token>| match ending {
+                Some(Token::Eol(Eol::Lf)) => "\\n",
+                Some(Token::Eol(Eol::Crlf)) => "\\r\\n",
+                Some(Token::Eol(Eol::Cr)) => "\\r",
+                Some(Token::Separator(Separator::Nel)) => "<NEL>",
+                Some(Token::Separator(Separator::Ls)) => "<LS>",
+                Some(Token::Separator(Separator::Ps)) => "<PS>",
+                Some(Token::Separator(Separator::Ff)) => "\\f",
+                Some(Token::Malformed(Malformed::CrCrLf)) => "\\r\\r\\n",
+                Some(Token::Malformed(Malformed::LfCr)) => "\\n\\r",
+                Some(Token::Malformed(Malformed::CrLfCrLf)) => "\\r\\n\\r\\n",
+                Some(Token::Unit(_)) | None => "",
+            };
+            let mut print = |prefix: &str, ending: Option<Token>| -> io::Result<()> {
+                output.write_all(prefix.as_bytes())?;
+                output.write_all(encoding.decode_lossy(line).as_bytes())?;
+                writeln!(output, "{}", visible(ending))?;
+                if ending.is_none() {
+                    writeln!(output, "\\ No newline at end of file")?;
+                }
+                io::Result::Ok(())
+            };
+            match ending.map(|old| (old, conversion.ending(old))) {
+                Some((old, Ok(new))) if old != new => {
+                    print("-", Some(old))?;
+                    print("+", Some(new))?;
+                },
+                _ => print(" ", ending)?,
+            }
+        }
+    }
+    Ok(true)
+}
+
+/// Split code units into lines and their line ending tokens, as recognized by `conversion`.
+/// The last line has no line ending if the units do not end with one.
+fn split_lines<'a>(units: &'a [u32], conversion: &Conversion) -> Vec<(&'a [u32], Option<Token>)> {
+    let encoding = conversion.encoding();
+    let mut lines = Vec::new();
+    let (mut start, mut pos) = (0, 0);
+    for token in conversion.tokens(units.iter().copied()) {
+        if let Token::Unit(_) = token {
+            pos += 1;
+            continue;
+        }
+        lines.push((&units[start..pos], Some(token)));
+        pos += token.len(encoding);
+        start = pos;
+    }
+    if start < units.len() {
+        lines.push((&units[start..], None));
+    }
+    lines
+}
+
+/// End-of-line sequence.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Eol {
+    Lf,
+    Crlf,
+    Cr,
+}
+
+impl Eol {
+    pub fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Eol::Lf => &[LF],
+            Eol::Crlf => &[CR, LF],
+            Eol::Cr => &[CR],
+        }
+    }
+}
+
+/// Settings for converting the content of a file.
+#[derive(Debug, Clone, Copy)]
+pub struct Conversion {
+    /// Target end-of-line sequence.
+    pub eol: Eol,
+    /// Detected from the content if not set.
+    pub encoding: Option<Encoding>,
+    /// Whether Unicode line separators are recognized.
+    pub unicode: bool,
+    /// What to do with Unicode line separators, if recognized.
+    pub separators: Policy,
+    /// What to do with form feeds. They are plain text if kept.
+    pub form_feed: Policy,
+    /// What to do with lone `CR`s. They are plain text if kept.
+    pub lone_cr: LoneCr,
+    /// Whether the malformed line endings `CR CR LF`, `LF CR` and `CRLF CRLF` are repaired into
+    /// a single line break each, before lone `CR`s are handled.
+    pub repair: bool,
+    /// What to do with the byte order mark.
+    pub bom: Bom,
+    /// What to do with the line endings at the end of content.
+    pub final_newline: FinalNewline,
+    /// Whether trailing spaces and tabs are removed from lines.
+    pub trim: bool,
+    /// Maximum number of consecutive blank lines.
+    pub max_blank_lines: Option<usize>,
+    /// Whether blank lines at the start and end of content are removed.
+    pub trim_blank_lines: bool,
+    /// Indentation to convert to, if any.
+    pub indent: Option<Indent>,
+    /// Number of columns of a tab, for converting indentation.
+    pub tab_width: usize,
+    /// Whether all tabs are expanded when indenting with spaces, not only the leading ones.
+    pub all_tabs: bool,
+}
+
+impl Conversion {
+    /// Conversion to `eol` with a detected encoding and no other line breaks recognized.
+    pub fn new(eol: Eol) -> Self {
+        Self {
+            eol,
+            encoding: None,
+            unicode: false,
+            separators: Policy::Normalize,
+            form_feed: Policy::Keep,
+            lone_cr: LoneCr::Convert,
+            repair: false,
+            bom: Bom::Keep,
+            final_newline: FinalNewline::Keep,
+            trim: false,
+            max_blank_lines: None,
+            trim_blank_lines: false,
+            indent: None,
+            tab_width: DEFAULT_TAB_WIDTH,
+            all_tabs: false,
+        }
+    }
+
+    /// Detect the encoding from the first block of content, unless it is already set.
+    pub fn detect(self, block: &[u8]) -> Self {
+        Self {
+            encoding: self.encoding.or_else(|| Encoding::detect(block)),
+            ..self
+        }
+    }
+
+    pub fn encoding(&self) -> Encoding {
+        self.encoding.unwrap_or_default()
+    }
+
+    /// Whether only line endings of single byte content are converted, besides the byte order
+    /// mark and the final newline, so that the content can be converted in chunks.
+    fn is_chunked(&self) -> bool {
+        !self.encoding().is_wide()
+            && !self.unicode
+            && self.form_feed == Policy::Keep
+            && !self.trim
+            && self.max_blank_lines.is_none()
+            && !self.trim_blank_lines
+            && self.indent.is_none()
+            && !self.repair
+    }
+
+    /// Split code units into tokens, recognizing the separators enabled for the conversion.
+    fn tokens<U: Iterator<Item = u32>>(&self, units: U) -> Tokens<U> {
+        Tokens {
+            separators: self.unicode,
+            form_feed: self.form_feed != Policy::Keep,
+            lone_cr: self.lone_cr == LoneCr::Convert,
+            repair: self.repair,
+            utf8: !self.encoding().is_wide(),
+            ..Tokens::new(units)
+        }
+    }
+
+    /// The line ending that replaces `ending`, or an error if the policy rejects it.
+    fn ending(&self, ending: Token) -> Result<Token> {
+        let policy = match ending {
+            Token::Separator(Separator::Ff) => self.form_feed,
+            Token::Separator(_) => self.separators,
+            _ => Policy::Normalize,
+        };
+        match (ending, policy) {
+            (Token::Separator(separator), Policy::Reject) => Err(Error::Rejected(separator)),
+            (Token::Separator(_), Policy::Keep) | (Token::Unit(_), _) => Ok(ending),
+            _ => Ok(Token::Eol(self.eol)),
+        }
+    }
+
+    /// Convert the line endings in `bytes`, operating on the code units of the encoding.
+    /// The byte order mark and the final newline are handled in the same pass.
+    /// Returns the changes made besides line endings.
+    fn transform(
+        &self,
+        mut bytes: impl Iterator<Item = u8>,
+        mut writer: impl Write,
+    ) -> Result<Changes> {
+        let mut changes = Changes::default();
+        let encoding = self.encoding();
+        let bom = encoding.bom();
+        let head = bytes.by_ref().take(bom.len()).collect::<Vec<_>>();
+        let present = head == bom;
+        changes.bom = self.write_bom(present, &mut writer)?;
+        let (head, mut offset) = match present {
+            true => (Vec::new(), bom.len() as u64),
+            false => (head, 0),
+        };
+        let mut units = Units::new(head.into_iter().chain(bytes), encoding);
+
+        // Line breaks are held back until the next content, as it is not known before whether
+        // they end blank lines or the content. Likewise spaces and tabs while trimming, until it
+        // is known whether they are trailing.
+        let mut held = Vec::new();
+        let mut blanks = Vec::new();
+        let mut started = false;
+        let max_blank = |blank: usize| blank.min(self.max_blank_lines.unwrap_or(usize::MAX));
+        let mut reindent = self
+            .indent
+            .map(|indent| Reindent::new(indent, self.tab_width, self.all_tabs, encoding));
+        let mut queue = Vec::new();
+        let mut lone_crs = LoneCrs::default();
+        // `None` marks the end of content, for indentation that is still held back.
+        for token in self.tokens(&mut units).map(Some).chain([None]) {
+            if let Some(token) = token {
+                // Lone `CR`s are units unless they are line breaks.
+                if self.lone_cr == LoneCr::Error && token == Token::Unit(CR.into()) {
+                    lone_crs.push(offset);
+                }
+                if let Token::Malformed(_) = token {
+                    changes.repaired += 1;
+                }
+                offset += (token.len(encoding) * encoding.unit_len()) as u64;
+            }
+            let token = token.map(|token| self.ending(token)).transpose()?;
+            match &mut reindent {
+                Some(reindent) => reindent.push(token, &mut queue),
+                None => queue.extend(token),
+            }
+            for token in queue.drain(..) {
+                match token {
+                    Token::Unit(unit) if self.trim && is_blank(unit) => blanks.push(unit),
+                    Token::Unit(unit) => {
+                        // The first held break ends the previous line, the rest are blank lines.
+                        let (end, blank) = match started {
+                            true => (held.len().min(1), held.len().saturating_sub(1)),
+                            false => (0, held.len()),
+                        };
+                        let kept = match !started && self.trim_blank_lines {
+                            true => 0,
+                            false => max_blank(blank),
+                        };
+                        changes.blank_lines += blank - kept;
+                        for &token in &held[..end + kept] {
+                            self.write_token(&mut writer, token)?;
+                        }
+                        held.clear();
+                        for unit in blanks.drain(..) {
+                            encoding.write_unit(&mut writer, unit)?;
+                        }
+                        encoding.write_unit(&mut writer, unit)?;
+                        started = true;
+                    },
+                    token => {
+                        if !blanks.is_empty() {
+                            blanks.clear();
+                            changes.trimmed += 1;
+                        }
+                        held.push(token);
+                    },
+                }
+            }
+        }
+        if !blanks.is_empty() {
+            changes.trimmed += 1;
+        }
+        changes.reindented = reindent.map_or(0, |reindent| reindent.lines);
+
+        let (end, blank) = match started {
+            true => (held.len().min(1), held.len().saturating_sub(1)),
+            false => (0, held.len()),
+        };
+        let kept = match self.final_newline {
+            FinalNewline::Strip => {
+                if !held.is_empty() {
+                    changes.final_newline = Some(FinalNewline::Strip);
+                }
+                0
+            },
+            _ if self.trim_blank_lines => {
+                changes.blank_lines += blank;
+                end
+            },
+            _ => {
+                changes.blank_lines += blank - max_blank(blank);
+                end + max_blank(blank)
+            },
+        };
+        for &token in &held[..kept] {
+            self.write_token(&mut writer, token)?;
+        }
+        if self.final_newline == FinalNewline::Ensure && started && held.is_empty() {
+            encoding.write_ascii(&mut writer, self.eol.as_bytes())?;
+            changes.final_newline = Some(FinalNewline::Ensure);
+        }
+        // An incomplete code unit at the end is kept as is.
+        writer.write_all(&units.remainder)?;
+        lone_crs.check()?;
+        Ok(changes)
+    }
+
+    /// Convert the line endings of single byte content read in chunks, see [`Self::is_chunked`].
+    /// Returns the changes made besides line endings.
+    fn transform_chunks(
+        &self,
+        mut reader: impl BufRead,
+        mut writer: impl Write,
+    ) -> Result<Changes> {
+        let bom = self.encoding().bom();
+        let mut head = Vec::with_capacity(bom.len());
+        while head.len() < bom.len() {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            let len = chunk.len().min(bom.len() - head.len());
+            head.extend_from_slice(&chunk[..len]);
+            reader.consume(len);
+        }
+        let present = head == bom;
+        let mut changes = Changes {
+            bom: self.write_bom(present, &mut writer)?,
+            ..Changes::default()
+        };
+
+        let mut chunked = Chunked::new(self.eol, self.lone_cr);
+        if !present {
+            chunked.push(&head, &mut writer)?;
+        }
+        loop {
+            let chunk = reader.fill_buf()?;
+            if chunk.is_empty() {
+                break;
+            }
+            chunked.push(chunk, &mut writer)?;
+            let len = chunk.len();
+            reader.consume(len);
+        }
+        changes.final_newline = chunked.finish(self.final_newline, &mut writer)?;
+        Ok(changes)
+    }
+
+    /// Write the byte order mark if it is added or kept, given whether the content starts with
+    /// it. Returns the change made to it, if any.
+    fn write_bom(&self, present: bool, writer: &mut impl Write) -> io::Result<Option<Bom>> {
+        if self.bom == Bom::Add || (self.bom == Bom::Keep && present) {
+            writer.write_all(self.encoding().bom())?;
+        }
+        io::Result::Ok(match self.bom {
+            Bom::Add if !present => Some(Bom::Add),
+            Bom::Strip if present => Some(Bom::Strip),
+            _ => None,
+        })
+    }
+
+    fn write_token(&self, writer: &mut impl Write, token: Token) -> io::Result<()> {
+        let encoding = self.encoding();
+        match token {
+            Token::Unit(unit) => encoding.write_unit(writer, unit),
+            Token::Eol(eol) => encoding.write_ascii(writer, eol.as_bytes()),
+            Token::Separator(separator) => separator.write(encoding, writer),
+            Token::Malformed(malformed) => encoding.write_ascii(writer, malformed.as_bytes()),
+        }
+    }
+}
+
+/// Item of a code unit stream split into line endings and other code units.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Token {
+    Unit(u32),
+    Eol(Eol),
+    Separator(Separator),
+    Malformed(Malformed),
+}
+
+impl Token {
+    /// Number of code units of the token in an encoding.
+    fn len(&self, encoding: Encoding) -> usize {
+        match self {
+            Token::Unit(_) => 1,
+            Token::Eol(eol) => eol.as_bytes().len(),
+            Token::Separator(separator) => separator.len(encoding),
+            Token::Malformed(malformed) => malformed.as_bytes().len(),
+        }
+    }
+}
+
+/// Malformed line ending left by broken conversions, which is repaired into a single line break.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Malformed {
+    /// `CR CR LF`, e.g. from converting `LF` to `CRLF` in content that has `CRLF` already.
+    CrCrLf,
+    /// `LF CR`, a reversed `CRLF`.
+    LfCr,
+    /// `CRLF CRLF`, e.g. from converting both `CR` and `LF` to `CRLF`. It is indistinguishable
+    /// from a blank line, so pairs of `CRLF` are collapsed from the start of a run.
+    CrLfCrLf,
+}
+
+impl Malformed {
+    fn as_bytes(&self) -> &'static [u8] {
+        match self {
+            Malformed::CrCrLf => &[CR, CR, LF],
+            Malformed::LfCr => &[LF, CR],
+            Malformed::CrLfCrLf => &[CR, LF, CR, LF],
+        }
+    }
+}
+
+/// Iterator that recognizes `LF`, `CRLF` and lone `CR` sequences in a code unit stream, and
+/// optionally Unicode line separators, form feeds and malformed line endings.
+struct Tokens<U: Iterator<Item = u32>> {
+    units: U,
+    /// Units read ahead to match multi-unit sequences.
+    pending: VecDeque<u32>,
+    separators: bool,
+    form_feed: bool,
+    /// Whether lone `CR`s are line breaks, or units otherwise.
+    lone_cr: bool,
+    repair: bool,
+    /// Whether units are UTF-8 bytes, so separators are decoded from their multi-byte sequences.
+    utf8: bool,
+}
+
+impl<U: Iterator<Item = u32>> Tokens<U> {
+    fn new(units: U) -> Self {
+        Self {
+            units,
+            pending: VecDeque::new(),
+            separators: false,
+            form_feed: false,
+            lone_cr: true,
+            repair: false,
+            utf8: true,
+        }
+    }
+
+    /// Look at the `n`th unit ahead without consuming it.
+    fn peek(&mut self, n: usize) -> Option<u32> {
+        while self.pending.len() <= n {
+            self.pending.push_back(self.units.next()?);
+        }
+        Some(self.pending[n])
+    }
+
+    /// Consume `n` units that have been peeked at.
+    fn skip(&mut self, n: usize) {
+        self.pending.drain(..n);
+    }
+
+    /// Recognize a malformed line ending that starts with `unit`.
+    fn malformed(&mut self, unit: u32) -> Option<Malformed> {
+        let is = |unit: Option<u32>, byte: u8| unit == Some(byte.into());
+        // An `LF CR LF` is an `LF` and a `CRLF` instead.
+        let malformed = if unit == LF.into() && is(self.peek(0), CR) && !is(self.peek(1), LF) {
+            Malformed::LfCr
+        } else if unit != CR.into() {
+            return None;
+        } else if is(self.peek(0), CR) && is(self.peek(1), LF) {
+            Malformed::CrCrLf
+        } else if is(self.peek(0), LF) && is(self.peek(1), CR) && is(self.peek(2), LF) {
+            Malformed::CrLfCrLf
+        } else {
+            return None;
+        };
+        self.skip(malformed.as_bytes().len() - 1);
+        Some(malformed)
+    }
+
+    fn separator(&mut self, unit: u32) -> Option<Separator> {
+        if !self.utf8 {
+            return match unit {
+                0x85 => Some(Separator::Nel),
+                0x2028 => Some(Separator::Ls),
+                0x2029 => Some(Separator::Ps),
+                _ => None,
+            };
+        }
+        let separator = match (unit, self.peek(0)) {
+            (0xC2, Some(0x85)) => {
+                self.skip(1);
+                return Some(Separator::Nel);
+            },
+            (0xE2, Some(0x80)) => match self.peek(1)? {
+                0xA8 => Separator::Ls,
+                0xA9 => Separator::Ps,
+                _ => return None,
+            },
+            _ => return None,
+        };
+        self.skip(2);
+        Some(separator)
+    }
+}
+
+impl<U: Iterator<Item = u32>> Iterator for Tokens<U> {
+    type Item = Token;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let unit = match self.pending.pop_front() {
+            Some(unit) => unit,
+            None => self.units.next()?,
+        };
+        Some(
+            if self.repair
+                && let Some(malformed) = self.malformed(unit)
+            {
+                Token::Malformed(malformed)
+            } else if unit == LF.into() {
+                Token::Eol(Eol::Lf)
+            } else if unit == CR.into() {
+                if self.peek(0) == Some(LF.into()) {
+                    self.skip(1);
+                    Token::Eol(Eol::Crlf)
+                } else if self.lone_cr {
+                    Token::Eol(Eol::Cr)
+                } else {
+                    Token::Unit(unit)
+                }
+            } else if self.form_feed && unit == FF.into() {
+                Token::Separator(Separator::Ff)
+            } else if self.separators
+                && let Some(separator) = self.separator(unit)
+            {
+                Token::Separator(separator)
+            } else {
+                Token::Unit(unit)
+            },
+        )
+    }
+}
+
+/// Line break other than `LF`, `CRLF` or `CR`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    /// Next line, U+0085.
+    Nel,
+    /// Line separator, U+2028.
+    Ls,
+    /// Paragraph separator, U+2029.
+    Ps,
+    /// Form feed, U+000C.
+    Ff,
+}
+
+impl Separator {
+    fn char(&self) -> char {
+        match self {
+            Separator::Nel => '\u{0085}',
+            Separator::Ls => '\u{2028}',
+            Separator::Ps => '\u{2029}',
+            Separator::Ff => '\u{000C}',
+        }
+    }
+
+    /// Number of code units of the separator in an encoding.
+    fn len(&self, encoding: Encoding) -> usize {
+        match encoding {
+            Encoding::Utf8 => self.char().len_utf8(),
+            _ => 1,
+        }
+    }
+
+    fn write(&self, encoding: Encoding, writer: &mut impl Write) -> io::Result<()> {
+        match encoding {
+            Encoding::Utf8 => writer.write_all(self.char().encode_utf8(&mut [0; 4]).as_bytes()),
+            _ => encoding.write_unit(writer, self.char().into()),
+        }
+    }
+}
+
+impl std::fmt::Display for Separator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Separator::Nel => "NEL",
+            Separator::Ls => "LS",
+            Separator::Ps => "PS",
+            Separator::Ff => "FF",
+        })
+    }
+}
+
+/// What to do with a kind of line break.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Policy {
+    /// Convert it to the target end-of-line sequence.
+    Normalize,
+    /// Leave it as is.
+    #[default]
+    Keep,
+    /// Refuse to convert a file that contains it.
+    Reject,
+}
+
+impl std::str::FromStr for Policy {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "normalize" => Ok(Policy::Normalize),
+            "keep" => Ok(Policy::Keep),
+            "reject" => Ok(Policy::Reject),
+            _ => Err(ParseError("policy")),
+        }
+    }
+}
+
+impl std::fmt::Display for Policy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Policy::Normalize => "normalize",
+            Policy::Keep => "keep",
+            Policy::Reject => "reject",
+        })
+    }
+}
+
+/// What to do with lone `CR`s, which are not followed by an `LF`.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum LoneCr {
+    /// Convert them to the target end-of-line sequence, like other line endings.
+    #[default]
+    Convert,
+    /// Leave them as is, as text.
+    Keep,
+    /// Refuse to convert content that contains them.
+    Error,
+}
+
+impl std::str::FromStr for LoneCr {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "convert" => Ok(LoneCr::Convert),
+            "keep" => Ok(LoneCr::Keep),
+            "error" => Ok(LoneCr::Error),
+            _ => Err(ParseError("lone CR handling")),
+        }
+    }
+}
+
+impl std::fmt::Display for LoneCr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            LoneCr::Convert => "convert",
+            LoneCr::Keep => "keep",
+            LoneCr::Error => "error",
+        })
+    }
+}
+
+/// What to do with the byte order mark.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Bom {
+    Add,
+    Strip,
+    #[default]
+    Keep,
+}
+
+impl std::str::FromStr for Bom {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "add" => Ok(Bom::Add),
+            "strip" => Ok(Bom::Strip),
+            "keep" => Ok(Bom::Keep),
+            _ => Err(ParseError("BOM handling")),
+        }
+    }
+}
+
+impl std::fmt::Display for Bom {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Bom::Add => "add",
+            Bom::Strip => "strip",
+            Bom::Keep => "keep",
+        })
+    }
+}
+
+/// Whether a code unit is a space or a tab.
+fn is_blank(unit: u32) -> bool {
+    unit == u32::from(b' ') || unit == u32::from(b'\t')
+}
+
+/// What to do with the line endings at the end of content.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum FinalNewline {
+    Ensure,
+    Strip,
+    #[default]
+    Keep,
+}
+
+impl std::str::FromStr for FinalNewline {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "ensure" => Ok(FinalNewline::Ensure),
+            "strip" => Ok(FinalNewline::Strip),
+            "keep" => Ok(FinalNewline::Keep),
+            _ => Err(ParseError("final newline handling")),
+        }
+    }
+}
+
+impl std::fmt::Display for FinalNewline {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            FinalNewline::Ensure => "ensure",
+            FinalNewline::Strip => "strip",
+            FinalNewline::Keep => "keep",
+        })
+    }
+}
+
+/// Changes made by a conversion besides converting line endings.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct Changes {
+    pub bom: Option<Bom>,
+    pub final_newline: Option<FinalNewline>,
+    /// Number of lines with trailing whitespace removed.
+    pub trimmed: usize,
+    /// Number of blank lines removed.
+    pub blank_lines: usize,
+    /// Number of lines with converted indentation or tabs.
+    pub reindented: usize,
+    /// Number of malformed line endings repaired.
+    pub repaired: usize,
+}
+
+impl Changes {
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+}
+
+impl std::fmt::Display for Changes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut notes = Vec::new();
+        match self.bom {
+            Some(Bom::Add) => notes.push("BOM added".to_string()),
+            Some(Bom::Strip) => notes.push("BOM stripped".to_string()),
+            _ => {},
+        }
+        match self.final_newline {
+            Some(FinalNewline::Ensure) => notes.push("final newline added".to_string()),
+            Some(FinalNewline::Strip) => notes.push("final newline stripped".to_string()),
+            _ => {},
+        }
+        if self.trimmed > 0 {
+            notes.push(format!("{} lines trimmed", self.trimmed));
+        }
+        if self.blank_lines > 0 {
+            notes.push(format!("{} blank lines removed", self.blank_lines));
+        }
+        if self.reindented > 0 {
+            notes.push(format!("{} lines reindented", self.reindented));
+        }
+        if self.repaired > 0 {
+            notes.push(format!("{} line endings repaired", self.repaired));
+        }
+        write!(f, "{}", notes.join(", "))
+    }
+}
+
+/// Counts of line ending sequences found in content, and its encoding.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EolStats {
+    pub lf: usize,
+    pub crlf: usize,
+    pub cr: usize,
+    /// Counts of NEL, LS, PS and FF, if recognized, indexed by [`Separator`].
+    pub separators: [usize; 4],
+    pub encoding: Encoding,
+}
+
+impl EolStats {
+    fn scan(tokens: impl Iterator<Item = Token>) -> Self {
+        let mut stats = Self::default();
+        for token in tokens {
+            match token {
+                Token::Eol(Eol::Lf) => stats.lf += 1,
+                Token::Eol(Eol::Crlf) => stats.crlf += 1,
+                Token::Eol(Eol::Cr) => stats.cr += 1,
+                Token::Separator(separator) => stats.separators[separator as usize] += 1,
+                Token::Unit(_) | Token::Malformed(_) => {},
+            }
+        }
+        stats
+    }
+
+    fn add(&mut self, histogram: Histogram) {
+        self.lf += histogram.lf;
+        self.crlf += histogram.crlf;
+        self.cr += histogram.cr;
+    }
+
+    /// The only sequence used, if there are line endings and they are all the same.
+    pub fn uniform(&self) -> Option<Eol> {
+        if self.separators.iter().any(|&n| n > 0) {
+            return None;
+        }
+        match (self.lf, self.crlf, self.cr) {
+            (1.., 0, 0) => Some(Eol::Lf),
+            (0, 1.., 0) => Some(Eol::Crlf),
+            (0, 0, 1..) => Some(Eol::Cr),
+            _ => None,
+        }
+    }
+}
+
+impl std::fmt::Display for EolStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.uniform() {
+            Some(eol) => write!(f, "{eol}")?,
+            None if self.lf + self.crlf + self.cr + self.separators.iter().sum::<usize>() == 0 => {
+                write!(f, "none")?
+            },
+            None => write!(f, "mixed")?,
+        }
+        write!(f, " (LF: {}, CRLF: {}, CR: {}", self.lf, self.crlf, self.cr)?;
+        let separators = [Separator::Nel, Separator::Ls, Separator::Ps, Separator::Ff];
+        for (separator, count) in separators.iter().zip(self.separators) {
+            if count > 0 {
+                write!(f, ", {separator}: {count}")?;
+            }
+        }
+        write!(f, ")")
+    }
+}
+
+impl std::str::FromStr for Eol {
+    type Err = ParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "lf" => Ok(Eol::Lf),
+            "crlf" => Ok(Eol::Crlf),
+            "cr" => Ok(Eol::Cr),
+            _ => Err(ParseError("end-of-line sequence")),
+        }
+    }
+}
+
+impl std::fmt::Display for Eol {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", match self {
+            Eol::Lf => "LF",
+            Eol::Crlf => "CRLF",
+            Eol::Cr => "CR",
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn test(eol: Eol, input: &[u8]) -> Vec<u8> {
+        let mut out = Vec::new();
+        Conversion::new(eol)
+            .transform(input.bytes().map(|r| r.unwrap()), &mut out)
+            .unwrap();
+        out
+    }
+
+    #[test]
+    fn transform_lf() {
+        assert_eq!(test(Eol::Lf, b""), b"");
+        assert_eq!(test(Eol::Lf, b"abc"), b"abc");
+        assert_eq!(test(Eol::Lf, b"\n"), b"\n");
+        assert_eq!(test(Eol::Lf, b"\r\n"), b"\n");
+        assert_eq!(test(Eol::Lf, b"x\rx\n"), b"x\nx\n");
+        assert_eq!(test(Eol::Lf, b"\r\n\r"), b"\n\n");
+    }
+
+    #[test]
+    fn transform_crlf() {
+        assert_eq!(test(Eol::Crlf, b""), b"");
+        assert_eq!(test(Eol::Crlf, b"abc"), b"abc");
+        assert_eq!(test(Eol::Crlf, b"\n"), b"\r\n");
+        assert_eq!(test(Eol::Crlf, b"\r\n"), b"\r\n");
+        assert_eq!(test(Eol::Crlf, b"x\rx\n"), b"x\r\nx\r\n");
+        assert_eq!(test(Eol::Crlf, b"\r\n\r"), b"\r\n\r\n");
+    }
+
+    #[test]
+    fn transform_cr() {
+        assert_eq!(test(Eol::Cr, b""), b"");
+        assert_eq!(test(Eol::Cr, b"abc"), b"abc");
+        assert_eq!(test(Eol::Cr, b"\n"), b"\r");
+        assert_eq!(test(Eol::Cr, b"\r\n"), b"\r");
+        assert_eq!(test(Eol::Cr, b"x\rx\n"), b"x\rx\r");
+        assert_eq!(test(Eol::Cr, b"\r\n\r"), b"\r\r");
+    }
+
+    #[test]
+    fn convert_reader() {
+        struct Failing;
+        impl Read for Failing {
+            fn read(&mut self, _: &mut [u8]) -> io::Result<usize> {
+                Err(io::Error::other("failed"))
+            }
+        }
+        let mut out = Vec::new();
+        let changes = convert(&b"a\r\nb"[..], &mut out, Conversion::new(Eol::Lf)).unwrap();
+        assert_eq!(out, b"a\nb");
+        assert!(changes.is_empty());
+        assert!(matches!(
+            convert(Failing, Vec::new(), Conversion::new(Eol::Lf)),
+            Err(Error::Io(_))
+        ));
+        let stats = scan(&b"a\r\nb\n"[..], Conversion::new(Eol::Lf)).unwrap();
+        assert_eq!(stats.to_string(), "mixed (LF: 1, CRLF: 1, CR: 0)");
+    }
+
+    #[test]
+    fn transform_chunks() {
+        /// Reader that returns one byte at a time, so that every byte is a chunk.
+        struct Trickle<'a>(&'a [u8]);
+        impl Read for Trickle<'_> {
+            fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+                self.0.by_ref().take(1).read(buf)
+            }
+        }
+        let inputs: [&[u8]; 5] = [
+            b"",
+            b"\xEF\xBB\xBF\r\n",
+            b"\xEF\xBB",
+            b"a\r\r\nb\n\r",
+            b"\n\r\na",
+        ];
+        for eol in [Eol::Lf, Eol::Crlf, Eol::Cr] {
+            for (bom, final_newline) in [
+                (Bom::Keep, FinalNewline::Keep),
+                (Bom::Add, FinalNewline::Ensure),
+                (Bom::Strip, FinalNewline::Strip),
+            ] {
+                let chunked = Conversion {
+                    bom,
+                    final_newline,
+                    ..Conversion::new(eol)
+                };
+                assert!(chunked.is_chunked());
+                for input in inputs {
+                    let mut expected = Vec::new();
+                    let changes = chunked
+                        .transform(input.iter().copied(), &mut expected)
+                        .unwrap();
+                    let mut out = Vec::new();
+                    let mut reader = Trickle(input);
+                    assert_eq!(convert(&mut reader, &mut out, chunked).unwrap(), changes);
+                    assert_eq!(out, expected, "{input:?} to {eol}");
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn unchanged() {
+        let inputs: [&[u8]; 10] = [
+            b"",
+            b"\xEF\xBB\xBF",
+            b"a\nb",
+            b"a\n\n",
+            b"\xEF\xBBa\n",
+            b"a\r\n",
+            b"a\rb\r",
+            b"\xEF\xBB\xBFa\r\nb",
+            b"a\rb\n",
+            b"a\r\n\r",
+        ];
+        for final_newline in [
+            FinalNewline::Keep,
+            FinalNewline::Ensure,
+            FinalNewline::Strip,
+        ] {
+            for (eol, bom, lone_cr) in [Bom::Keep, Bom::Add, Bom::Strip]
+                .into_iter()
+                .flat_map(|bom| [(Eol::Lf, bom), (Eol::Crlf, bom), (Eol::Cr, bom)])
+                .flat_map(|(eol, bom)| {
+                    [LoneCr::Convert, LoneCr::Keep, LoneCr::Error]
+                        .map(|lone_cr| (eol, bom, lone_cr))
+                })
+            {
+                let conversion = Conversion {
+                    bom,
+                    final_newline,
+                    lone_cr,
+                    ..Conversion::new(eol)
+                };
+                for input in inputs {
+                    if is_unchanged(input, conversion) {
+                        let (output, _) = convert_slice(input, conversion).unwrap();
+                        assert_eq!(
+                            output, input,
+                            "{input:?} to {eol}, {bom}, {final_newline}, {lone_cr}"
+                        );
+                    }
+                }
+            }
+        }
+        let lf = Conversion::new(Eol::Lf);
+        assert!(is_unchanged(b"a\nb\n", lf));
+        assert!(!is_unchanged(b"a\r\nb\n", lf));
+        assert!(!is_unchanged(b"a\nb\n", Conversion::new(Eol::Crlf)));
+        assert!(is_unchanged(b"a\r\nb\r\n", Conversion::new(Eol::Crlf)));
+        assert!(is_unchanged(b"a\rb", Conversion::new(Eol::Cr)));
+        assert!(!is_unchanged(b"a \n", Conversion { trim: true, ..lf }));
+        let keep = Conversion {
+            lone_cr: LoneCr::Keep,
+            ..lf
+        };
+        assert!(is_unchanged(b"a\rb\n", keep));
+        assert!(!is_unchanged(b"a\rb\r\n", keep));
+    }
+
+    #[test]
+    fn eol_stats() {
+        fn scan(input: &[u8]) -> String {
+            EolStats::scan(Tokens::new(input.iter().map(|&b| b.into()))).to_string()
+        }
+        assert_eq!(scan(b""), "none (LF: 0, CRLF: 0, CR: 0)");
+        assert_eq!(scan(b"abc"), "none (LF: 0, CRLF: 0, CR: 0)");
+        assert_eq!(scan(b"a\nb\n"), "LF (LF: 2, CRLF: 0, CR: 0)");
+        assert_eq!(scan(b"a\r\nb\r\n"), "CRLF (LF: 0, CRLF: 2, CR: 0)");
+        assert_eq!(scan(b"a\rb\r"), "CR (LF: 0, CRLF: 0, CR: 2)");
+        assert_eq!(scan(b"\r\n\r\n\n"), "mixed (LF: 1, CRLF: 2, CR: 0)");
+        assert_eq!(scan(b"\r\r\n"), "mixed (LF: 0, CRLF: 1, CR: 1)");
+        let tokens = Conversion {
+            unicode: true,
+            ..Conversion::new(Eol::Lf)
+        }
+        .tokens("a\nb\u{2028}".bytes().map(u32::from));
+        assert_eq!(
+            EolStats::scan(tokens).to_string(),
+            "mixed (LF: 1, CRLF: 0, CR: 0, LS: 1)"
+        );
+    }
+
+    #[test]
+    fn binary_detection() {
+        assert!(!looks_binary(b""));
+        assert!(!looks_binary(b"fn main() {\r\n\tprintln!();\r\n}\n"));
+        assert!(!looks_binary("ääkköset\n".as_bytes()));
+        assert!(looks_binary(b"\x89PNG\r\n\x1A\n\0\0\0\rIHDR"));
+        assert!(looks_binary(b"ab\x01\x02\x03\x04"));
+    }
+
+    #[test]
+    fn unified_diff() {
+        let diff = |content: &[u8]| {
+            let mut out = Vec::new();
+            diff(content, "a.txt", Conversion::new(Eol::Lf), &mut out).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        assert_eq!(diff(b"1\n2\n"), "");
+        assert_eq!(
+            diff(b"1\n2\n3\n4\n5\r\n6\n7\n8\n9\n10\n11\n12\n13\r14"),
+            "--- a/a.txt\n+++ b/a.txt\n@@ -2,7 +2,7 @@\n 2\\n\n 3\\n\n 4\\n\n-5\\r\\n\n+5\\n\n \
+             6\\n\n 7\\n\n 8\\n\n@@ -10,5 +10,5 @@\n 10\\n\n 11\\n\n 12\\n\n-13\\r\n+13\\n\n \
+             14\n\\ No newline at end of file\n"
+        );
+    }
+
+    #[test]
+    fn lines() {
+        let a = u32::from(b'a');
+        let b = u32::from(b'b');
+        let (cr, lf) = (u32::from(CR), u32::from(LF));
+        let conversion = Conversion::new(Eol::Lf);
+        assert_eq!(split_lines(&[], &conversion), vec![]);
+        assert_eq!(split_lines(&[a], &conversion), vec![(&[a][..], None)]);
+        assert_eq!(split_lines(&[a, cr, lf, cr, b, lf], &conversion), vec![
+            (&[a][..], Some(Token::Eol(Eol::Crlf))),
+            (&[][..], Some(Token::Eol(Eol::Cr))),
+            (&[b][..], Some(Token::Eol(Eol::Lf))),
+        ]);
+    }
+
+    #[test]
+    fn transform_separators() {
+        let transform = |conversion: Conversion, input: &str| {
+            let mut out = Vec::new();
+            conversion
+                .transform(input.bytes(), &mut out)
+                .map(|_| String::from_utf8(out).unwrap())
+                .map_err(|e| e.to_string())
+        };
+        let input = "a\u{0085}b\u{2028}c\u{2029}d\x0Ce\r\n\u{2027}";
+        let conversion = Conversion::new(Eol::Lf);
+        assert_eq!(
+            transform(conversion, input).unwrap(),
+            input.replace("\r\n", "\n")
+        );
+        let conversion = Conversion {
+            unicode: true,
+            ..conversion
+        };
+        assert_eq!(
+            transform(conversion, input).unwrap(),
+            "a\nb\nc\nd\x0Ce\n\u{2027}"
+        );
+        let conversion = Conversion {
+            separators: Policy::Keep,
+            form_feed: Policy::Normalize,
+            ..conversion
+        };
+        assert_eq!(
+            transform(conversion, input).unwrap(),
+            "a\u{0085}b\u{2028}c\u{2029}d\ne\n\u{2027}"
+        );
+        let conversion = Conversion {
+            separators: Policy::Reject,
+            ..conversion
+        };
+        assert_eq!(
+            transform(conversion, input).unwrap_err(),
+            "Contains a rejected NEL line break"
+        );
+        assert_eq!(transform(conversion, "a\x0Cb").unwrap(), "a\nb");
+    }
+
+    #[test]
+    fn transform_lone_cr() {
+        let input = b"\ra\r\rb\n\r\nc \r\r\n\r";
+        let keep = Conversion {
+            lone_cr: LoneCr::Keep,
+            ..Conversion::new(Eol::Crlf)
+        };
+        // Converting units and converting in chunks treat lone `CR`s the same.
+        for conversion in [keep, Conversion {
+            unicode: true,
+            ..keep
+        }] {
+            assert_eq!(
+                convert_slice(input, conversion).unwrap().0,
+                b"\ra\r\rb\r\n\r\nc \r\r\n\r"
+            );
+        }
+        let trimmed = Conversion { trim: true, ..keep };
+        assert_eq!(
+            convert_slice(b"a \r\n\r \n", trimmed).unwrap().0,
+            b"a\r\n\r\r\n"
+        );
+
+        let error = |conversion: Conversion, input: &[u8]| {
+            let conversion = Conversion {
+                lone_cr: LoneCr::Error,
+                ..conversion
+            };
+            convert_slice(input, conversion).unwrap_err().to_string()
+        };
+        for conversion in [keep, Conversion {
+            unicode: true,
+            ..keep
+        }] {
+            assert_eq!(
+                error(conversion, input),
+                "Contains 5 lone CRs at byte offsets 0, 2, 3, 10, 13"
+            );
+        }
+        let lone = b"\ra\r".repeat(6);
+        assert_eq!(
+            error(keep, &lone),
+            "Contains 12 lone CRs at byte offsets 0, 2, 3, 5, 6, 8, 9, 11, 12, 14 and 2 more"
+        );
+        let utf16 = b"\xFF\xFEa\0\r\0\r\0\n\0\r\0";
+        assert_eq!(
+            error(keep, utf16),
+            "Contains 2 lone CRs at byte offsets 4, 10"
+        );
+        let mut output = Vec::new();
+        let conversion = Conversion {
+            lone_cr: LoneCr::Error,
+            ..keep
+        };
+        assert_eq!(
+            diff(b"a\r\nb\r", "a", conversion, &mut output)
+                .unwrap_err()
+                .to_string(),
+            "Contains a lone CR at byte offset 4"
+        );
+    }
+
+    #[test]
+    fn transform_repair() {
+        let repair = Conversion {
+            repair: true,
+            ..Conversion::new(Eol::Lf)
+        };
+        for (input, expected, repaired) in [
+            ("a\r\r\nb\r\r\n", "a\nb\n", 2),
+            ("a\n\rb\n\r", "a\nb\n", 2),
+            ("a\n\r\nb", "a\n\nb", 0),
+            ("a\r\n\r\nb\r\n\r\n\r\n\r\nc\r\n", "a\nb\n\nc\n", 3),
+            ("a\r\r\r\nb\rc", "a\n\nb\nc", 1),
+        ] {
+            let (output, changes) = convert_slice(input.as_bytes(), repair).unwrap();
+            assert_eq!(String::from_utf8(output).unwrap(), expected, "{input:?}");
+            assert_eq!(changes.repaired, repaired, "{input:?}");
+        }
+        let (output, changes) = convert_slice(b"a\r\r\nb", Conversion::new(Eol::Lf)).unwrap();
+        assert_eq!((output, changes.repaired), (b"a\n\nb".to_vec(), 0));
+
+        let strict = Conversion {
+            lone_cr: LoneCr::Error,
+            final_newline: FinalNewline::Strip,
+            ..repair
+        };
+        let (output, changes) = convert_slice(b"a\n\rb\r\r\n", strict).unwrap();
+        assert_eq!(output, b"a\nb");
+        assert_eq!(
+            changes.to_string(),
+            "final newline stripped, 2 line endings repaired"
+        );
+        let mut output = Vec::new();
+        assert!(diff(b"a\r\r\nb\n", "a", repair, &mut output).unwrap());
+        assert!(
+            String::from_utf8(output)
+                .unwrap()
+                .contains("-a\\r\\r\\n\n+a\\n\n")
+        );
+    }
+
+    #[test]
+    fn transform_bom() {
+        let transform = |bom: Bom, input: &[u8]| {
+            let conversion = Conversion {
+                bom,
+                ..Conversion::new(Eol::Lf)
+            };
+            let mut out = Vec::new();
+            let changes = conversion
+                .transform(input.iter().copied(), &mut out)
+                .unwrap();
+            (out, changes.bom)
+        };
+        let bom = "\u{FEFF}a\r\n".as_bytes();
+        assert_eq!(transform(Bom::Keep, bom), ("\u{FEFF}a\n".into(), None));
+        assert_eq!(transform(Bom::Add, bom), ("\u{FEFF}a\n".into(), None));
+        assert_eq!(
+            transform(Bom::Strip, bom),
+            (b"a\n".into(), Some(Bom::Strip))
+        );
+        assert_eq!(transform(Bom::Keep, b"a"), (b"a".into(), None));
+        assert_eq!(transform(Bom::Strip, b"a"), (b"a".into(), None));
+        assert_eq!(
+            transform(Bom::Add, b""),
+            ("\u{FEFF}".into(), Some(Bom::Add))
+        );
+    }
+
+    #[test]
+    fn transform_final_newline() {
+        use FinalNewline::{Ensure, Keep, Strip};
+        let transform = |final_newline: FinalNewline, input: &[u8]| {
+            let conversion = Conversion {
+                final_newline,
+                ..Conversion::new(Eol::Crlf)
+            };
+            let mut out = Vec::new();
+            let changes = conversion
+                .transform(input.iter().copied(), &mut out)
+                .unwrap();
+            (String::from_utf8(out).unwrap(), changes.to_string())
+        };
+        assert_eq!(transform(Keep, b"a\nb"), ("a\r\nb".into(), "".into()));
+        assert_eq!(
+            transform(Ensure, b"a\nb"),
+            ("a\r\nb\r\n".into(), "final newline added".into())
+        );
+        assert_eq!(transform(Ensure, b"a\n"), ("a\r\n".into(), "".into()));
+        assert_eq!(transform(Ensure, b""), ("".into(), "".into()));
+        assert_eq!(
+            transform(Strip, b"a\n\nb\n\r\n"),
+            ("a\r\n\r\nb".into(), "final newline stripped".into())
+        );
+        assert_eq!(transform(Strip, b"a"), ("a".into(), "".into()));
+    }
+
+    #[test]
+    fn transform_trim() {
+        let transform = |final_newline: FinalNewline, input: &[u8]| {
+            let conversion = Conversion {
+                trim: true,
+                final_newline,
+                ..Conversion::new(Eol::Lf)
+            };
+            let mut out = Vec::new();
+            let changes = conversion
+                .transform(input.iter().copied(), &mut out)
+                .unwrap();
+            (String::from_utf8(out).unwrap(), changes.trimmed)
+        };
+        assert_eq!(transform(FinalNewline::Keep, b"a b\n"), ("a b\n".into(), 0));
+        assert_eq!(
+            transform(FinalNewline::Keep, b"a \t\r\n\t\r\n b  "),
+            ("a\n\n b".into(), 3)
+        );
+        assert_eq!(transform(FinalNewline::Ensure, b"a\n  "), ("a\n".into(), 1));
+        assert_eq!(
+            transform(FinalNewline::Strip, b"a \n \n b\n  \n"),
+            ("a\n\n b".into(), 3)
+        );
+    }
+
+    #[test]
+    fn transform_blank_lines() {
+        let transform = |max_blank_lines: Option<usize>, trim_blank_lines: bool, input: &[u8]| {
+            let conversion = Conversion {
+                trim: true,
+                max_blank_lines,
+                trim_blank_lines,
+                ..Conversion::new(Eol::Lf)
+            };
+            let mut out = Vec::new();
+            let changes = conversion
+                .transform(input.iter().copied(), &mut out)
+                .unwrap();
+            (String::from_utf8(out).unwrap(), changes.blank_lines)
+        };
+        let input = b"\n \na\n\n\n\nb\r\n\n\tc\n\n\n";
+        assert_eq!(
+            transform(None, false, input),
+            ("\n\na\n\n\n\nb\n\n\tc\n\n\n".into(), 0)
+        );
+        assert_eq!(
+            transform(Some(1), false, input),
+            ("\na\n\nb\n\n\tc\n\n".into(), 4)
+        );
+        assert_eq!(transform(Some(0), true, input), ("a\nb\n\tc\n".into(), 8));
+        assert_eq!(transform(None, true, b"a"), ("a".into(), 0));
+        assert_eq!(transform(None, true, b"\n\n"), ("".into(), 2));
+        assert_eq!(transform(Some(1), false, b"\n\n"), ("\n".into(), 1));
+    }
+
+    #[test]
+    fn transform_utf16() {
+        let utf16le = |text: &str| {
+            text.encode_utf16()
+                .flat_map(u16::to_le_bytes)
+                .collect::<Vec<_>>()
+        };
+        let conversion = Conversion {
+            encoding: Some(Encoding::Utf16Le),
+            ..Conversion::new(Eol::Crlf)
+        };
+        let mut out = Vec::new();
+        let input = utf16le("\u{FEFF}a\nä\r\n\u{0D0A}\r");
+        conversion.transform(input.into_iter(), &mut out).unwrap();
+        assert_eq!(out, utf16le("\u{FEFF}a\r\nä\r\n\u{0D0A}\r\n"));
+
+        // Detected from the byte order mark, with an incomplete unit kept at the end.
+        let mut input = utf16le("\u{FEFF}a\n");
+        input.push(0x0A);
+        let conversion = Conversion::new(Eol::Crlf).detect(&input);
+        let mut out = Vec::new();
+        conversion.transform(input.into_iter(), &mut out).unwrap();
+        let mut expected = utf16le("\u{FEFF}a\r\n");
+        expected.push(0x0A);
+        assert_eq!(out, expected);
+    }
+}
@@ -2,14 +2,27 @@ use std::collections::HashSet;
 use std::fs::{File, OpenOptions};
 use std::io::prelude::*;
 use std::io::{self, BufReader, BufWriter};
-use std::path::Path;
+use std::path::{Component, Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::{env, fs};
 
 use anyhow::{Ok, Result};
-use clap::{Arg, ArgAction, Command};
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use error::{Context, Error, Errors, Operation};
+use memmap2::Mmap;
+use newl::{Changes, Conversion, DEFAULT_TAB_WIDTH, Encoding, Eol, SNIFF_LEN};
+use rayon::prelude::*;
 
-const CR: u8 = 0x0D;
-const LF: u8 = 0x0A;
+mod editorconfig;
+mod error;
+mod gitattributes;
+
+/// Exit code used by `--check` when any file would be converted.
+const EXIT_NEEDS_CONVERSION: i32 = 2;
+
+/// Number of files processed in parallel before reporting their outcomes, which bounds the
+/// memory held by buffered output.
+const BATCH_LEN: usize = 256;
 
 fn cli() -> Command {
     Command::new(clap::crate_name!())
@@ -18,23 +31,7 @@ fn cli() -> Command {
         .author(clap::crate_authors!())
         .args_conflicts_with_subcommands(true)
         .arg_required_else_help(true)
-        .arg(
-            Arg::new("include")
-                .help("Include filepaths with a pattern. (appending)")
-                .value_name("PATTERN")
-                .num_args(1..)
-                .required(true)
-                .action(ArgAction::Append),
-        )
-        .arg(
-            Arg::new("exclude")
-                .short('e')
-                .long("exclude")
-                .help("Exclude filepaths with a pattern. (appending)")
-                .value_name("PATTERN")
-                .num_args(1..)
-                .action(ArgAction::Append),
-        )
+        .args(path_args())
         .arg(
             Arg::new("eol")
                 .short('l')
@@ -47,12 +44,173 @@ fn cli() -> Command {
                 .global(true),
         )
         .arg(
-            Arg::new("case-sensitive")
-                .short('c')
-                .long("case-sensitive")
+            Arg::new("encoding")
+                .long("encoding")
+                .help(
+                    "Set the encoding of input files. `auto` detects UTF-16 and UTF-32 from a \
+                     byte order mark or the content.",
+                )
+                .value_name("ENCODING")
+                .value_parser([
+                    "auto", "UTF-8", "UTF-16LE", "UTF-16BE", "UTF-32LE", "UTF-32BE",
+                ])
+                .default_value("auto")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("bom")
+                .long("bom")
+                .help("Add, strip or keep the byte order mark of the encoding.")
+                .value_name("BOM")
+                .value_parser(["add", "strip", "keep"])
+                .default_value("keep")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("unicode")
+                .short('u')
+                .long("unicode")
+                .help(
+                    "Treat Unicode line separators NEL (U+0085), LS (U+2028) and PS (U+2029) as \
+                     line breaks. UTF-8 input is decoded for them.",
+                )
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("separators")
+                .long("separators")
+                .help("Set what to do with Unicode line separators, with --unicode.")
+                .value_name("POLICY")
+                .value_parser(["normalize", "keep", "reject"])
+                .default_value("normalize")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("form-feed")
+                .long("form-feed")
+                .help(
+                    "Set what to do with form feeds. `keep` leaves them as text, otherwise they \
+                     are treated as line breaks.",
+                )
+                .value_name("POLICY")
+                .value_parser(["normalize", "keep", "reject"])
+                .default_value("keep")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("lone-cr")
+                .long("lone-cr")
+                .help(
+                    "Set what to do with carriage returns not followed by a line feed. `keep` \
+                     leaves them as text, and `error` refuses to convert files that contain them.",
+                )
+                .value_name("POLICY")
+                .value_parser(["convert", "keep", "error"])
+                .default_value("convert")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("repair")
+                .long("repair")
+                .help(
+                    "Repair the malformed line endings `\\r\\r\\n`, `\\n\\r` and doubled \
+                     `\\r\\n\\r\\n` into a single line ending each. Doubled ones are also blank \
+                     lines in CRLF files, so only use it on double-spaced files.",
+                )
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("final-newline")
+                .long("final-newline")
+                .help(
+                    "Ensure that non-empty content ends with a line ending, strip trailing line \
+                     endings, or keep them as they are.",
+                )
+                .value_name("FINAL")
+                .value_parser(["ensure", "strip", "keep"])
+                .default_value("keep")
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("trim-trailing-whitespace")
+                .long("trim-trailing-whitespace")
+                .help("Remove spaces and tabs at the end of lines.")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("trim-exclude")
+                .long("trim-exclude")
+                .help("Don't trim trailing whitespace in files matching a pattern. (appending)")
+                .value_name("PATTERN")
+                .requires("trim-trailing-whitespace")
+                .action(ArgAction::Append),
+        )
+        .arg(
+            Arg::new("max-blank-lines")
+                .long("max-blank-lines")
+                .help("Collapse runs of consecutive blank lines to at most N lines.")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .global(true),
+        )
+        .arg(
+            Arg::new("trim-blank-lines")
+                .long("trim-blank-lines")
+                .help("Remove blank lines at the start and end of content.")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("indent")
+                .long("indent")
+                .help("Convert the leading indentation of lines to tabs or spaces.")
+                .value_name("INDENT")
+                .value_parser(["tabs", "spaces"])
+                .ignore_case(true)
+                .global(true),
+        )
+        .arg(
+            Arg::new("tab-width")
+                .long("tab-width")
+                .help("Set the number of columns of a tab, for --indent.")
+                .value_name("N")
+                .value_parser(clap::builder::RangedU64ValueParser::<usize>::new().range(1..))
+                .default_value("4")
+                .global(true),
+        )
+        .arg(
+            Arg::new("all-tabs")
+                .long("all-tabs")
+                .help("Expand all tabs to spaces, not only the leading ones, with --indent spaces.")
+                .requires("indent")
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("editorconfig")
+                .long("editorconfig")
                 .help(
-                    "Use case sensitive matching in patterns (on Windows). NOTE: Does nothing on \
-                     exact paths.",
+                    "Use the `end_of_line` property from .editorconfig files for each file. Falls \
+                     back to --eol for files without one.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("gitattributes")
+                .long("gitattributes")
+                .help(
+                    "Use the `eol` and `text` attributes from .gitattributes files for each file. \
+                     Files with `-text` (or `binary`) are skipped. Takes precedence over \
+                     --editorconfig.",
                 )
                 .action(ArgAction::SetTrue),
         )
@@ -63,6 +221,73 @@ fn cli() -> Command {
                 .help("Print filepaths that would be affected, without modifying files.")
                 .action(ArgAction::SetTrue),
         )
+        .arg(
+            Arg::new("check")
+                .long("check")
+                .help(
+                    "Print filepaths that would be changed, without modifying files. Exits with \
+                     code 2 if any file needs conversion.",
+                )
+                .conflicts_with_all(["dry-run", "debug"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("binary")
+                .long("binary")
+                .help(
+                    "Also convert files that look like binary files, which are skipped by default.",
+                )
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("diff")
+                .long("diff")
+                .help(
+                    "Print a unified diff of the line endings that would change, without \
+                     modifying files.",
+                )
+                .conflicts_with_all(["dry-run", "check", "debug"])
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("preserve-mtime")
+                .long("preserve-mtime")
+                .help("Keep the modification and access times of converted files.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("mmap-threshold")
+                .long("mmap-threshold")
+                .help("Memory-map files of at least this size instead of reading them in chunks.")
+                .value_name("MIB")
+                .value_parser(clap::value_parser!(u64))
+                .default_value("16"),
+        )
+        .arg(
+            Arg::new("no-mmap")
+                .long("no-mmap")
+                .help("Never memory-map files.")
+                .action(ArgAction::SetTrue),
+        )
+        .arg(
+            Arg::new("jobs")
+                .short('j')
+                .long("jobs")
+                .help("Number of files to process at once, or 0 for one per CPU.")
+                .value_name("N")
+                .value_parser(clap::value_parser!(usize))
+                .default_value("1"),
+        )
+        .arg(
+            Arg::new("fail-fast")
+                .long("fail-fast")
+                .help(
+                    "Stop at the first file that fails, instead of reporting all failures at the \
+                     end.",
+                )
+                .global(true)
+                .action(ArgAction::SetTrue),
+        )
         .arg(
             Arg::new("debug")
                 .short('d')
@@ -98,41 +323,452 @@ fn cli() -> Command {
                         .action(ArgAction::SetTrue),
                 ),
         )
+        .subcommand(
+            Command::new("detect")
+                .about("Report the line ending sequences found in files.")
+                .args(path_args())
+                .after_help("Exclusions take precedence over inclusions."),
+        )
         .after_help("Exclusions take precedence over inclusions.")
 }
 
+/// Arguments for selecting filepaths, shared by the base command and subcommands.
+fn path_args() -> Vec<Arg> {
+    vec![
+        Arg::new("include")
+            .help("Include filepaths with a pattern. (appending)")
+            .value_name("PATTERN")
+            .num_args(1..)
+            .required(true)
+            .action(ArgAction::Append),
+        Arg::new("exclude")
+            .short('e')
+            .long("exclude")
+            .help("Exclude filepaths with a pattern. (appending)")
+            .value_name("PATTERN")
+            .num_args(1..)
+            .action(ArgAction::Append),
+        Arg::new("case-sensitive")
+            .short('c')
+            .long("case-sensitive")
+            .help(
+                "Use case sensitive matching in patterns (on Windows). NOTE: Does nothing on \
+                 exact paths.",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("no-ignore")
+            .long("no-ignore")
+            .help(
+                "Don't respect .gitignore, .ignore and hidden file rules when walking directories.",
+            )
+            .action(ArgAction::SetTrue),
+        Arg::new("max-depth")
+            .long("max-depth")
+            .help("Limit the depth of walked directories.")
+            .value_name("DEPTH")
+            .value_parser(clap::value_parser!(usize)),
+    ]
+}
+
+/// Collect the filepaths selected by the arguments from [`path_args`].
+/// Directories are walked recursively, honoring ignore files unless disabled.
+/// Files and directories that cannot be read are reported to `errors`.
+fn matched_paths(matches: &ArgMatches, errors: &mut Errors) -> Vec<PathBuf> {
+    let glob_options = glob::MatchOptions {
+        case_sensitive: matches.get_flag("case-sensitive"),
+        ..Default::default()
+    };
+    let walk = |dir: &Path, errors: &mut Errors| {
+        let mut builder = ignore::WalkBuilder::new(dir);
+        builder
+            .standard_filters(!matches.get_flag("no-ignore"))
+            .max_depth(matches.get_one::<usize>("max-depth").copied())
+            .filter_entry(|entry| entry.file_name() != ".git")
+            .sort_by_file_name(|a, b| a.cmp(b));
+        builder
+            .build()
+            .filter_map(|entry| entry.map_err(|e| errors.push(Error::Walk(e))).ok())
+            .filter(|entry| entry.file_type().is_some_and(|t| t.is_file()))
+            .map(|entry| normalize(entry.path()))
+            .collect::<Vec<_>>()
+    };
+    // Invalid patterns are fatal, unreadable paths matched by valid ones are not.
+    let glob = |pattern: &str, errors: &mut Errors| {
+        glob::glob_with(pattern, glob_options)
+            .unwrap_or_else(|e| exit_with_error(e))
+            .filter_map(|p| {
+                p.map_err(|e| {
+                    errors.push(Error::File {
+                        path: e.path().to_path_buf(),
+                        operation: Operation::Walk,
+                        source: e.into_error().into(),
+                    })
+                })
+                .ok()
+            })
+            .collect::<Vec<_>>()
+    };
+
+    let excluded = match matches.get_many::<String>("exclude") {
+        Some(values) => values
+            .flat_map(|p| glob(p, errors))
+            .map(|p| normalize(&p))
+            .collect::<HashSet<_>>(),
+        None => HashSet::new(),
+    };
+    let is_excluded = |path: &Path| path.ancestors().any(|p| excluded.contains(p));
+
+    // This ensures that glob patterns are correct before doing any work.
+    let included = match matches.get_many::<String>("include") {
+        Some(values) => values.flat_map(|p| glob(p, errors)).collect::<Vec<_>>(),
+        None => {
+            eprintln!("No included files.");
+            Vec::new()
+        },
+    };
+
+    let mut seen = HashSet::new();
+    included
+        .into_iter()
+        .flat_map(|p| {
+            if p.is_dir() {
+                walk(&p, errors)
+            } else if p.is_file() {
+                vec![normalize(&p)]
+            } else {
+                Vec::new()
+            }
+        })
+        .filter(|p| !is_excluded(p))
+        .filter(|p| seen.insert(p.clone()))
+        .collect()
+}
+
+/// Remove `.` components, so that paths from globs and directory walks compare equal.
+fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .collect()
+}
+
 fn exit_with_error(msg: impl std::fmt::Display) -> ! {
     eprintln!("{msg}");
     std::process::exit(1);
 }
 
+/// Parse the value of an argument, or get the default if it is not set.
+fn parse_arg<T: std::str::FromStr<Err: std::fmt::Display> + Default>(
+    matches: &ArgMatches,
+    id: &str,
+) -> T {
+    matches
+        .get_one::<String>(id)
+        .map(|value| value.parse().unwrap_or_else(|e| exit_with_error(e)))
+        .unwrap_or_default()
+}
+
 /// Read stdin and write to `output` with the set end-of-line sequence.
 /// `debug` flag sets output bytes `\r` and `\n` to be displayed as text.
-fn stdin_to_output(output: impl Write + 'static, eol: Eol, debug: bool) -> Result<()> {
+fn stdin_to_output(
+    output: impl Write + 'static,
+    conversion: Conversion,
+    debug: bool,
+) -> Result<()> {
     // NOTE: Windows stdin impl only supports UTF-8.
-    let mut output = writer(output, debug);
-    let stdin = io::stdin().lock();
-    let bytes = stdin
-        .bytes()
-        .map(|r| r.unwrap_or_else(|e| exit_with_error(e)));
-    let transform = eol.transform_fn();
-    transform(bytes, &mut output)?;
-    output.flush()?;
+    let output = writer(output, debug);
+    newl::convert(io::stdin().lock(), output, conversion)?;
     Ok(())
 }
 
 /// Apply a conversion to a file, this assumes that path is an accessible file.
-fn file_to_output(path: &Path, mut output: impl Write, eol: Eol) -> Result<()> {
+/// Files of at least `mmap` bytes are memory-mapped. Returns the changes made besides line endings.
+fn file_to_output(
+    path: &Path,
+    output: impl Write,
+    conversion: Conversion,
+    mmap: Option<u64>,
+) -> Result<Changes, Error> {
     debug_assert!(path.is_file());
-    let input = File::open(path)?;
-    let input = BufReader::new(input);
-    let input = input
-        .bytes()
-        .map(|r| r.unwrap_or_else(|e| exit_with_error(e)));
-    let transform = eol.transform_fn();
-    transform(input, &mut output)?;
-    output.flush()?;
-    Ok(())
+    Input::open(path, mmap)
+        .on_file(path, Operation::Read)?
+        .convert(output, conversion)
+        .on_file(path, Operation::Convert)
+}
+
+/// Content of a file to convert, memory-mapped if it is large enough.
+enum Input {
+    Mapped(Mmap),
+    Read(File),
+}
+
+impl Input {
+    /// Open a file, and map it if it has at least `mmap` bytes.
+    fn open(path: &Path, mmap: Option<u64>) -> io::Result<Self> {
+        let file = File::open(path)?;
+        match mmap {
+            Some(threshold) if file.metadata()?.len() >= threshold.max(1) => {
+                // SAFETY: The mapping is only read, and files are replaced instead of written in
+                // place. Like reading, it does not guard against other processes truncating or
+                // writing the file at the same time, which may fault or produce torn output.
+                let map = unsafe { Mmap::map(&file)? };
+                io::Result::Ok(Input::Mapped(map))
+            },
+            _ => io::Result::Ok(Input::Read(file)),
+        }
+    }
+
+    /// Whether converting the content certainly leaves it unchanged. This is known without
+    /// converting only for mapped content, see [`newl::is_unchanged`].
+    fn is_unchanged(&self, conversion: Conversion) -> bool {
+        matches!(self, Input::Mapped(map) if newl::is_unchanged(map, conversion))
+    }
+
+    /// Returns the changes made besides line endings.
+    fn convert(self, output: impl Write, conversion: Conversion) -> newl::Result<Changes> {
+        match self {
+            Input::Mapped(map) => newl::convert_slice_to(&map, output, conversion),
+            Input::Read(file) => newl::convert(file, output, conversion),
+        }
+    }
+
+    /// Convert the content of the file at `path` into `output`, comparing it to the original.
+    /// Returns the changes made besides line endings, or `None` if the content would not change.
+    fn convert_compared(
+        self,
+        path: &Path,
+        output: impl Write,
+        conversion: Conversion,
+    ) -> Result<Option<Changes>, Error> {
+        let (changes, changed) = match self {
+            Input::Mapped(map) => {
+                let mut output = CompareWriter::new(&map[..], output);
+                let changes = newl::convert_slice_to(&map, &mut output, conversion)
+                    .on_file(path, Operation::Convert)?;
+                (changes, output.changed())
+            },
+            Input::Read(file) => {
+                let original = File::open(path).on_file(path, Operation::Read)?;
+                let mut output = CompareWriter::new(BufReader::new(original), output);
+                let changes = newl::convert(file, &mut output, conversion)
+                    .on_file(path, Operation::Convert)?;
+                (changes, output.changed())
+            },
+        };
+        Result::Ok(changed.on_file(path, Operation::Read)?.then_some(changes))
+    }
+}
+
+/// Read the first [`SNIFF_LEN`] bytes of a file.
+fn first_block(path: &Path) -> io::Result<Vec<u8>> {
+    let mut block = Vec::with_capacity(SNIFF_LEN);
+    File::open(path)?
+        .take(SNIFF_LEN as u64)
+        .read_to_end(&mut block)?;
+    io::Result::Ok(block)
+}
+
+/// Check whether a file looks like a binary file, based on its first [`SNIFF_LEN`] bytes.
+fn is_binary(path: &Path) -> io::Result<bool> {
+    io::Result::Ok(newl::looks_binary(&first_block(path)?))
+}
+
+/// Check whether converting a file would change any of its bytes.
+/// Files of at least `mmap` bytes are memory-mapped, and scanned first if that can tell.
+fn needs_conversion(path: &Path, conversion: Conversion, mmap: Option<u64>) -> Result<bool, Error> {
+    let input = Input::open(path, mmap).on_file(path, Operation::Read)?;
+    if input.is_unchanged(conversion) {
+        return Result::Ok(false);
+    }
+    let changes = input.convert_compared(path, io::sink(), conversion)?;
+    Result::Ok(changes.is_some())
+}
+
+/// Writer that passes everything through to `inner`, while comparing it against the bytes of
+/// `original`.
+struct CompareWriter<R: BufRead, W: Write> {
+    original: R,
+    inner: W,
+    changed: bool,
+}
+
+impl<R: BufRead, W: Write> CompareWriter<R, W> {
+    fn new(original: R, inner: W) -> Self {
+        Self {
+            original,
+            inner,
+            changed: false,
+        }
+    }
+
+    /// Whether the written bytes differ from `original`, including its remaining bytes.
+    fn changed(&mut self) -> io::Result<bool> {
+        io::Result::Ok(self.changed || !self.original.fill_buf()?.is_empty())
+    }
+}
+
+impl<R: BufRead, W: Write> Write for CompareWriter<R, W> {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let len = self.inner.write(buf)?;
+        if !self.changed {
+            let mut expected = vec![0; len];
+            match self.original.read_exact(&mut expected) {
+                io::Result::Ok(()) => self.changed = expected != buf[..len],
+                Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => self.changed = true,
+                Err(e) => return Err(e),
+            }
+        }
+        io::Result::Ok(len)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.inner.flush()
+    }
+}
+
+/// Print the lines of a file whose line endings would change as a unified diff.
+/// Returns whether there were any changes.
+fn diff_file(path: &Path, conversion: Conversion, output: impl Write) -> Result<bool, Error> {
+    let content = fs::read(path).on_file(path, Operation::Read)?;
+    newl::diff(&content, &path.display().to_string(), conversion, output)
+        .on_file(path, Operation::Convert)
+}
+
+/// Restore the permissions, ownership (where possible) and optionally the timestamps of a
+/// rewritten file from its original metadata.
+fn restore_metadata(file: &File, metadata: &fs::Metadata, preserve_mtime: bool) -> io::Result<()> {
+    if preserve_mtime {
+        let times = fs::FileTimes::new()
+            .set_accessed(metadata.accessed()?)
+            .set_modified(metadata.modified()?);
+        file.set_times(times)?;
+    }
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        // Changing ownership usually requires privileges, so failing is not an error.
+        _ = std::os::unix::fs::fchown(file, Some(metadata.uid()), Some(metadata.gid()));
+    }
+    // Permissions go last, as changing ownership may clear setuid and setgid bits.
+    file.set_permissions(metadata.permissions())
+}
+
+/// Convert a file in place, returning the changes made besides line endings if its content changed.
+/// Files that would not change are left untouched. Files of at least `mmap` bytes are
+/// memory-mapped, and scanned first to skip them without writing anything if that can tell.
+///
+/// The output is written to a temporary file in the same directory, which is synced to disk and
+/// then atomically renamed over the original. If that is not possible, e.g. the directory is not
+/// writable or the file is locked, the output is copied over the original instead.
+fn convert_in_place(
+    path: &Path,
+    conversion: Conversion,
+    preserve_mtime: bool,
+    mmap: Option<u64>,
+) -> Result<Option<Changes>, Error> {
+    let metadata = fs::metadata(path).on_file(path, Operation::Read)?;
+    let input = Input::open(path, mmap).on_file(path, Operation::Read)?;
+    if input.is_unchanged(conversion) {
+        return Result::Ok(None);
+    }
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let temp = match tempfile::Builder::new().prefix(".newl").tempfile_in(dir) {
+        io::Result::Ok(temp) => temp,
+        Err(_) => tempfile::NamedTempFile::new().on_file(path, Operation::Write)?,
+    };
+    let output = BufWriter::new(temp.as_file());
+    // The mapping of the original is dropped here, before it is replaced.
+    let Some(changes) = input.convert_compared(path, output, conversion)? else {
+        return Result::Ok(None);
+    };
+    temp.as_file()
+        .sync_all()
+        .and_then(|()| restore_metadata(temp.as_file(), &metadata, preserve_mtime))
+        .on_file(path, Operation::Write)?;
+
+    if let Err(e) = temp.persist(path) {
+        fs::copy(e.file.path(), path)
+            .and_then(|_| OpenOptions::new().write(true).open(path))
+            .and_then(|file| {
+                restore_metadata(&file, &metadata, preserve_mtime)?;
+                file.sync_all()
+            })
+            .on_file(path, Operation::Replace)?;
+    }
+    Result::Ok(Some(changes))
+}
+
+/// Result of processing a file, reported in the order of the files after processing a batch of
+/// them in parallel.
+enum Outcome {
+    /// Whether the file needs conversion, when checking.
+    Check(bool),
+    /// The file needs conversion, but contains a line break rejected by its policy, or lone `CR`s.
+    Rejected(Error),
+    /// Changes that the conversion would make besides line endings.
+    DryRun(Changes),
+    /// Unified diff of the line endings that would change, empty if none.
+    Diff(Vec<u8>),
+    /// Converted content, and the changes made besides line endings.
+    Debug(Vec<u8>, Changes),
+    /// Changes made to a file converted in place, if its content changed.
+    InPlace(Option<Changes>),
+}
+
+/// Apply `f` to the items on the worker pool, returning the results in the order of the items.
+/// When failing fast, items are skipped after a failure, leaving `None` in their place.
+fn par_map<T: Sync, R: Send>(
+    items: &[T],
+    fail_fast: bool,
+    f: impl Fn(&T) -> Result<R, Error> + Sync,
+) -> Vec<Option<Result<R, Error>>> {
+    let failed = AtomicBool::new(false);
+    items
+        .par_iter()
+        .map(|item| {
+            if fail_fast && failed.load(Ordering::Relaxed) {
+                return None;
+            }
+            let result = f(item);
+            if result.is_err() {
+                failed.store(true, Ordering::Relaxed);
+            }
+            Some(result)
+        })
+        .collect()
+}
+
+/// Resolves the target end-of-line sequence for each file.
+struct Targets {
+    eol: Eol,
+    editorconfig: Option<editorconfig::EditorConfig>,
+    gitattributes: Option<gitattributes::GitAttributes>,
+}
+
+impl Targets {
+    /// Returns `None` if the file must not be converted.
+    fn eol(&mut self, path: &Path) -> Result<Option<Eol>, Error> {
+        if let Some(gitattributes) = &mut self.gitattributes {
+            match gitattributes
+                .target(path)
+                .on_file(path, Operation::Resolve)?
+            {
+                gitattributes::Target::Skip => return Result::Ok(None),
+                gitattributes::Target::Eol(eol) => return Result::Ok(Some(eol)),
+                gitattributes::Target::Unspecified => {},
+            }
+        }
+        if let Some(editorconfig) = &mut self.editorconfig
+            && let Some(eol) = editorconfig
+                .end_of_line(path)
+                .on_file(path, Operation::Resolve)?
+        {
+            return Result::Ok(Some(eol));
+        }
+        Result::Ok(Some(self.eol))
+    }
 }
 
 fn writer<W: Write + 'static>(writer: W, debug: bool) -> Box<dyn Write> {
@@ -143,10 +779,12 @@ fn writer<W: Write + 'static>(writer: W, debug: bool) -> Box<dyn Write> {
         impl<W: Write> Write for DebugWriter<W> {
             fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
                 for &byte in buf {
-                    if byte == LF {
+                    if byte == b'\n' {
                         self.writer.write_all(b"\\n")?;
-                    } else if byte == CR {
+                    } else if byte == b'\r' {
                         self.writer.write_all(b"\\r")?;
+                    } else if byte == b'\t' {
+                        self.writer.write_all(b"\\t")?;
                     } else {
                         self.writer.write_all(&[byte])?;
                     }
@@ -173,10 +811,62 @@ fn main() -> Result<()> {
         .unwrap_or_else(|| exit_with_error("Missing end-of-line sequence"))
         .parse()
         .unwrap_or_else(|e| exit_with_error(e));
+    let encoding: Option<Encoding> = match matches.get_one::<String>("encoding") {
+        Some(value) if !value.eq_ignore_ascii_case("auto") => {
+            Some(value.parse().unwrap_or_else(|e| exit_with_error(e)))
+        },
+        _ => None,
+    };
+    let conversion = Conversion {
+        encoding,
+        unicode: matches.get_flag("unicode"),
+        separators: parse_arg(&matches, "separators"),
+        form_feed: parse_arg(&matches, "form-feed"),
+        lone_cr: parse_arg(&matches, "lone-cr"),
+        repair: matches.get_flag("repair"),
+        bom: parse_arg(&matches, "bom"),
+        final_newline: parse_arg(&matches, "final-newline"),
+        trim: matches.get_flag("trim-trailing-whitespace"),
+        max_blank_lines: matches.get_one::<usize>("max-blank-lines").copied(),
+        trim_blank_lines: matches.get_flag("trim-blank-lines"),
+        indent: matches
+            .get_one::<String>("indent")
+            .map(|value| value.parse().unwrap_or_else(|e| exit_with_error(e))),
+        tab_width: matches
+            .get_one::<usize>("tab-width")
+            .copied()
+            .unwrap_or(DEFAULT_TAB_WIDTH),
+        all_tabs: matches.get_flag("all-tabs"),
+        ..Conversion::new(eol)
+    };
     let debug = matches.get_flag("debug");
+    let fail_fast = matches.get_flag("fail-fast");
+    let mut errors = Errors::new(fail_fast);
     if verbose {
         eprintln!("Target sequence: {eol}");
+        match encoding {
+            Some(encoding) => eprintln!("Encoding: {encoding}"),
+            None => eprintln!("Encoding: auto"),
+        }
+        eprintln!("Unicode: {}", conversion.unicode);
+        eprintln!("Separators: {}", conversion.separators);
+        eprintln!("Form feed: {}", conversion.form_feed);
+        eprintln!("Lone CR: {}", conversion.lone_cr);
+        eprintln!("Repair: {}", conversion.repair);
+        eprintln!("BOM: {}", conversion.bom);
+        eprintln!("Final newline: {}", conversion.final_newline);
+        eprintln!("Trim trailing whitespace: {}", conversion.trim);
+        match conversion.max_blank_lines {
+            Some(max) => eprintln!("Max blank lines: {max}"),
+            None => eprintln!("Max blank lines: unlimited"),
+        }
+        eprintln!("Trim blank lines: {}", conversion.trim_blank_lines);
+        match conversion.indent {
+            Some(indent) => eprintln!("Indent: {indent} (tab width {})", conversion.tab_width),
+            None => eprintln!("Indent: keep"),
+        }
         eprintln!("Output debug: {debug}");
+        eprintln!("Fail fast: {fail_fast}");
     }
 
     // Subcommands:
@@ -186,7 +876,7 @@ fn main() -> Result<()> {
                 eprintln!("Output target: stdout");
             }
             let stdout = io::stdout().lock();
-            stdin_to_output(stdout, eol, debug).unwrap_or_else(|e| exit_with_error(e));
+            stdin_to_output(stdout, conversion, debug).unwrap_or_else(|e| exit_with_error(e));
         } else if let Some(output) = sub_matches.get_one::<String>("file") {
             let output = std::path::PathBuf::from(output);
             if output.exists() && !output.is_file() {
@@ -201,175 +891,346 @@ fn main() -> Result<()> {
                 .truncate(true)
                 .open(output)
                 .unwrap_or_else(|e| exit_with_error(e));
-            stdin_to_output(file, eol, debug).unwrap_or_else(|e| exit_with_error(e));
+            stdin_to_output(file, conversion, debug).unwrap_or_else(|e| exit_with_error(e));
         } else if sub_matches.get_flag("stdout") {
             if verbose {
                 eprintln!("Output target: stdout");
             }
             let stdout = io::stdout().lock();
-            stdin_to_output(stdout, eol, debug).unwrap_or_else(|e| exit_with_error(e));
+            stdin_to_output(stdout, conversion, debug).unwrap_or_else(|e| exit_with_error(e));
         };
 
         return Ok(());
     }
 
+    if let Some(sub_matches) = matches.subcommand_matches("detect") {
+        let mut stdout = io::stdout().lock();
+        for path in matched_paths(sub_matches, &mut errors) {
+            match is_binary(&path).on_file(&path, Operation::Read) {
+                Result::Ok(false) => {},
+                Result::Ok(true) => {
+                    writeln!(stdout, "{}: binary", path.display())?;
+                    continue;
+                },
+                Err(e) => {
+                    errors.push(e);
+                    continue;
+                },
+            }
+            let stats = File::open(&path)
+                .and_then(|file| Result::Ok(newl::scan(file, conversion)))
+                .on_file(&path, Operation::Read)
+                .and_then(|stats| stats.on_file(&path, Operation::Read));
+            match stats {
+                Result::Ok(stats) if stats.encoding.is_wide() => {
+                    writeln!(stdout, "{}: {stats} [{}]", path.display(), stats.encoding)?;
+                },
+                Result::Ok(stats) => writeln!(stdout, "{}: {stats}", path.display())?,
+                Err(e) => errors.push(e),
+            }
+        }
+        stdout.flush()?;
+        errors.report();
+        return Ok(());
+    }
+
     // Base command:
     let dry_run = matches.get_flag("dry-run");
-    let glob_options = glob::MatchOptions {
-        case_sensitive: matches.get_flag("case-sensitive"),
-        ..Default::default()
+    let check = matches.get_flag("check");
+    let binary = matches.get_flag("binary");
+    let diff = matches.get_flag("diff");
+    let preserve_mtime = matches.get_flag("preserve-mtime");
+    let jobs = matches.get_one::<usize>("jobs").copied().unwrap_or(1);
+    let mmap = match matches.get_flag("no-mmap") {
+        true => None,
+        false => matches
+            .get_one::<u64>("mmap-threshold")
+            .map(|mib| mib.saturating_mul(1024 * 1024)),
     };
-
-    let excluded = match matches.get_many::<String>("exclude") {
-        Some(values) => values
-            .flat_map(|p| glob::glob_with(p, glob_options).unwrap_or_else(|e| exit_with_error(e)))
-            .map(|p| p.unwrap_or_else(|e| exit_with_error(e)))
-            .filter(|p| p.is_file())
-            .collect::<HashSet<_>>(),
-        None => HashSet::new(),
+    rayon::ThreadPoolBuilder::new()
+        .num_threads(jobs)
+        .build_global()
+        .unwrap_or_else(|e| exit_with_error(e));
+    let mut targets = Targets {
+        eol,
+        editorconfig: matches
+            .get_flag("editorconfig")
+            .then(editorconfig::EditorConfig::new),
+        gitattributes: matches
+            .get_flag("gitattributes")
+            .then(gitattributes::GitAttributes::new),
     };
-
-    // This ensures that glob patterns are correct before doing any work.
-    let paths = match matches.get_many::<String>("include") {
+    let mut paths = matched_paths(&matches, &mut errors);
+    let trim_exclude = match matches.get_many::<String>("trim-exclude") {
         Some(values) => values
-            .flat_map(|p| glob::glob_with(p, glob_options).unwrap_or_else(|e| exit_with_error(e)))
-            .map(|p| p.unwrap_or_else(|e| exit_with_error(e)))
-            .filter(|p| p.is_file())
-            .filter(|p| !excluded.contains(p))
-            .collect::<Vec<_>>(),
-        None => {
-            eprintln!("No included files.");
-            Vec::new()
-        },
+            .map(|p| glob::Pattern::new(p).unwrap_or_else(|e| exit_with_error(e)))
+            .collect(),
+        None => Vec::new(),
+    };
+    // Files have their own target sequence, and trimming may be disabled for them.
+    let file_conversion = |path: &Path, eol: Eol| {
+        let options = glob::MatchOptions {
+            case_sensitive: matches.get_flag("case-sensitive"),
+            ..Default::default()
+        };
+        let name = path.file_name().map(Path::new).unwrap_or(path);
+        let excluded = trim_exclude.iter().any(|pattern| {
+            pattern.matches_path_with(path, options) || pattern.matches_path_with(name, options)
+        });
+        Conversion {
+            eol,
+            trim: conversion.trim && !excluded,
+            ..conversion
+        }
     };
 
     if verbose {
         eprintln!("Dry-run: {dry_run}");
-        eprintln!("Case-sensitive: {}", glob_options.case_sensitive);
+        eprintln!("Check: {check}");
+        eprintln!("Diff: {diff}");
+        eprintln!("Binary: {binary}");
+        eprintln!("Preserve mtime: {preserve_mtime}");
+        eprintln!("Jobs: {jobs}");
+        match mmap {
+            Some(threshold) => eprintln!("Memory-map threshold: {threshold} bytes"),
+            None => eprintln!("Memory-map threshold: off"),
+        }
+        eprintln!("EditorConfig: {}", targets.editorconfig.is_some());
+        eprintln!("Gitattributes: {}", targets.gitattributes.is_some());
+        eprintln!("Case-sensitive: {}", matches.get_flag("case-sensitive"));
     }
 
-    let mut stdout = io::stdout().lock();
-    for path in paths {
-        if dry_run {
-            writeln!(stdout, "{}", path.display())?;
-            continue;
-        }
-        if verbose {
-            eprintln!("{}", path.display());
-        }
-        if debug {
-            let stdout = io::stdout().lock();
-            let output = writer(stdout, debug);
-            file_to_output(&path, output, eol)?;
-        } else {
-            let temp = temp_file::empty();
-            let output = OpenOptions::new().write(true).open(temp.path())?;
-            let output = BufWriter::new(output);
-            file_to_output(&path, output, eol)?;
-            fs::copy(temp.path(), path)?;
+    if !binary {
+        let binaries = par_map(&paths, fail_fast, |path| {
+            is_binary(path).on_file(path, Operation::Read)
+        });
+        let mut text = Vec::with_capacity(paths.len());
+        for (path, binary) in paths.into_iter().zip(binaries) {
+            match binary {
+                Some(Result::Ok(false)) => text.push(path),
+                Some(Result::Ok(true)) if verbose => {
+                    eprintln!("Skipping binary file: {}", path.display())
+                },
+                Some(Result::Ok(true)) | None => {},
+                Some(Err(e)) => errors.push(e),
+            }
         }
+        paths = text;
     }
 
-    Ok(())
-}
-
-/// End-of-line sequence.
-#[derive(Debug, Clone, Copy)]
-enum Eol {
-    Lf,
-    Crlf,
-    Cr,
-}
-
-impl Eol {
-    fn transform_fn<B: Iterator<Item = u8>, W: Write>(&self) -> fn(B, &mut W) -> Result<()> {
-        fn convert(
-            bytes: impl Iterator<Item = u8>,
-            mut writer: impl Write,
-            target: &[u8],
-        ) -> Result<()> {
-            let mut iter = bytes.peekable();
-            while let Some(byte) = iter.next() {
-                if byte == LF {
-                    writer.write_all(target)?;
-                } else if byte == CR {
-                    _ = iter.next_if(|&n| n == LF);
-                    writer.write_all(target)?;
-                } else {
-                    writer.write_all(&[byte])?;
+    // Target sequences are resolved in order, as the configuration files are cached.
+    let mut files = Vec::with_capacity(paths.len());
+    for path in paths {
+        match targets.eol(&path) {
+            Result::Ok(Some(eol)) => files.push((path, eol)),
+            Result::Ok(None) => {
+                if verbose {
+                    eprintln!("Skipping non-text file (gitattributes): {}", path.display());
                 }
-            }
-            Ok(())
-        }
-        match self {
-            Eol::Lf => |b, w| convert(b, w, &[LF]),
-            Eol::Crlf => |b, w| convert(b, w, &[CR, LF]),
-            Eol::Cr => |b, w| convert(b, w, &[CR]),
+            },
+            Err(e) => errors.push(e),
         }
     }
-}
 
-impl std::str::FromStr for Eol {
-    type Err = anyhow::Error;
+    let process = |(path, eol): &(PathBuf, Eol)| {
+        let conversion = file_conversion(path, *eol);
+        if check {
+            match needs_conversion(path, conversion, mmap) {
+                Err(e) if e.is_rejected() => Result::Ok(Outcome::Rejected(e)),
+                result => result.map(Outcome::Check),
+            }
+        } else if dry_run {
+            file_to_output(path, io::sink(), conversion, mmap).map(Outcome::DryRun)
+        } else if diff {
+            let mut output = Vec::new();
+            diff_file(path, conversion, &mut output)?;
+            Result::Ok(Outcome::Diff(output))
+        } else if debug {
+            let mut output = Vec::new();
+            let changes = file_to_output(path, &mut output, conversion, mmap)?;
+            Result::Ok(Outcome::Debug(output, changes))
+        } else {
+            convert_in_place(path, conversion, preserve_mtime, mmap).map(Outcome::InPlace)
+        }
+    };
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        match s.to_ascii_lowercase().as_str() {
-            "lf" => Ok(Eol::Lf),
-            "crlf" => Ok(Eol::Crlf),
-            "cr" => Ok(Eol::Cr),
-            _ => anyhow::bail!("Unknown end-of-line sequence"),
+    let mut stdout = io::stdout().lock();
+    let (mut count, mut changed, mut unchanged, mut trimmed) = (0, 0, 0, 0);
+    for batch in files.chunks(BATCH_LEN) {
+        let outcomes = par_map(batch, fail_fast, process);
+        for ((path, eol), outcome) in batch.iter().zip(outcomes) {
+            let Some(outcome) = outcome else {
+                continue;
+            };
+            if verbose && !check && !diff && !dry_run {
+                eprintln!("{} ({eol})", path.display());
+            }
+            match outcome {
+                Err(e) => errors.push(e),
+                Result::Ok(Outcome::Check(false)) => {},
+                Result::Ok(Outcome::Check(true)) => {
+                    writeln!(stdout, "{}", path.display())?;
+                    count += 1;
+                },
+                Result::Ok(Outcome::Rejected(e)) => {
+                    writeln!(stdout, "{}", path.display())?;
+                    eprintln!("{e}");
+                    count += 1;
+                },
+                Result::Ok(Outcome::DryRun(changes)) if changes.is_empty() => {
+                    writeln!(stdout, "{}", path.display())?
+                },
+                Result::Ok(Outcome::DryRun(changes)) => {
+                    writeln!(stdout, "{} ({changes})", path.display())?
+                },
+                Result::Ok(Outcome::Diff(output)) => stdout.write_all(&output)?,
+                Result::Ok(Outcome::Debug(output, changes)) => {
+                    let mut output_writer = writer(io::stdout().lock(), debug);
+                    output_writer.write_all(&output)?;
+                    output_writer.flush()?;
+                    if !changes.is_empty() {
+                        eprintln!("{} ({changes})", path.display());
+                    }
+                },
+                Result::Ok(Outcome::InPlace(Some(changes))) => {
+                    if changes.repaired > 0 {
+                        eprintln!("{} ({changes})", path.display());
+                    }
+                    changed += 1;
+                    trimmed += changes.trimmed;
+                },
+                Result::Ok(Outcome::InPlace(None)) => {
+                    if verbose {
+                        eprintln!("Unchanged: {}", path.display());
+                    }
+                    unchanged += 1;
+                },
+            }
         }
     }
-}
 
-impl std::fmt::Display for Eol {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{}", match self {
-            Eol::Lf => "LF",
-            Eol::Crlf => "CRLF",
-            Eol::Cr => "CR",
-        })
+    if check {
+        if verbose {
+            eprintln!("{count} of {} files need conversion", files.len());
+        }
+        stdout.flush()?;
+        errors.report();
+        if count > 0 {
+            std::process::exit(EXIT_NEEDS_CONVERSION);
+        }
+        return Ok(());
+    }
+    if !dry_run && !diff && !debug {
+        eprintln!("{changed} files changed, {unchanged} unchanged");
+        if verbose && conversion.trim {
+            eprintln!("{trimmed} lines trimmed");
+        }
     }
+    stdout.flush()?;
+    errors.report();
+
+    Ok(())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
 
-    fn test(eol: Eol, input: &[u8]) -> Vec<u8> {
-        let mut out = Vec::new();
-        let t = eol.transform_fn();
-        t(input.bytes().map(|r| r.unwrap()), &mut out).unwrap();
-        out
+    #[test]
+    fn compare_writer() {
+        fn changed(original: &[u8], written: &[u8]) -> bool {
+            let mut w = CompareWriter::new(original, Vec::new());
+            w.write_all(written).unwrap();
+            assert_eq!(w.inner, written);
+            w.changed().unwrap()
+        }
+        assert!(!changed(b"", b""));
+        assert!(!changed(b"a\nb\n", b"a\nb\n"));
+        assert!(changed(b"a\r\nb", b"a\nb"));
+        assert!(changed(b"a\nb", b"a\r\nb"));
+        assert!(changed(b"abc", b"ab"));
     }
 
     #[test]
-    fn transform_lf() {
-        assert_eq!(test(Eol::Lf, b""), b"");
-        assert_eq!(test(Eol::Lf, b"abc"), b"abc");
-        assert_eq!(test(Eol::Lf, b"\n"), b"\n");
-        assert_eq!(test(Eol::Lf, b"\r\n"), b"\n");
-        assert_eq!(test(Eol::Lf, b"x\rx\n"), b"x\nx\n");
-        assert_eq!(test(Eol::Lf, b"\r\n\r"), b"\n\n");
+    fn par_map_in_order() {
+        let items = (0..1000).collect::<Vec<_>>();
+        let fail = |&i: &i32| match i {
+            500 => Err(Error::File {
+                path: PathBuf::from("500"),
+                operation: Operation::Read,
+                source: io::Error::other("failed").into(),
+            }),
+            i => Result::Ok(i * 2),
+        };
+        let results = par_map(&items, false, fail);
+        assert_eq!(results.len(), items.len());
+        for (i, result) in results.into_iter().enumerate() {
+            match result {
+                Some(Result::Ok(n)) => assert_eq!(n, i as i32 * 2),
+                _ => assert_eq!(i, 500),
+            }
+        }
+        let results = rayon::ThreadPoolBuilder::new()
+            .num_threads(1)
+            .build()
+            .unwrap()
+            .install(|| par_map(&items, true, fail));
+        assert!(
+            results[..500]
+                .iter()
+                .all(|r| matches!(r, Some(Result::Ok(_))))
+        );
+        assert!(matches!(results[500], Some(Err(_))));
+        assert!(results[501..].iter().all(Option::is_none));
     }
 
     #[test]
-    fn transform_crlf() {
-        assert_eq!(test(Eol::Crlf, b""), b"");
-        assert_eq!(test(Eol::Crlf, b"abc"), b"abc");
-        assert_eq!(test(Eol::Crlf, b"\n"), b"\r\n");
-        assert_eq!(test(Eol::Crlf, b"\r\n"), b"\r\n");
-        assert_eq!(test(Eol::Crlf, b"x\rx\n"), b"x\r\nx\r\n");
-        assert_eq!(test(Eol::Crlf, b"\r\n\r"), b"\r\n\r\n");
+    fn normalize_paths() {
+        assert_eq!(
+            normalize(Path::new("./src/main.rs")),
+            Path::new("src/main.rs")
+        );
+        assert_eq!(
+            normalize(Path::new("src/./main.rs")),
+            Path::new("src/main.rs")
+        );
+        assert_eq!(normalize(Path::new("../src")), Path::new("../src"));
     }
 
+    #[cfg(unix)]
     #[test]
-    fn transform_cr() {
-        assert_eq!(test(Eol::Cr, b""), b"");
-        assert_eq!(test(Eol::Cr, b"abc"), b"abc");
-        assert_eq!(test(Eol::Cr, b"\n"), b"\r");
-        assert_eq!(test(Eol::Cr, b"\r\n"), b"\r");
-        assert_eq!(test(Eol::Cr, b"x\rx\n"), b"x\rx\r");
-        assert_eq!(test(Eol::Cr, b"\r\n\r"), b"\r\r");
+    fn convert_preserves_metadata() {
+        use std::os::unix::fs::PermissionsExt;
+        let dir = env::temp_dir().join(format!("newl-in-place-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("script.sh");
+        fs::write(&path, b"#!/bin/sh\r\necho\r\n").unwrap();
+        fs::set_permissions(&path, fs::Permissions::from_mode(0o754)).unwrap();
+        let times = fs::FileTimes::new().set_modified(std::time::UNIX_EPOCH);
+        File::options()
+            .write(true)
+            .open(&path)
+            .unwrap()
+            .set_times(times)
+            .unwrap();
+
+        assert!(
+            convert_in_place(&path, Conversion::new(Eol::Lf), true, None)
+                .unwrap()
+                .is_some()
+        );
+        assert!(
+            convert_in_place(&path, Conversion::new(Eol::Lf), false, Some(1))
+                .unwrap()
+                .is_none()
+        );
+        let metadata = fs::metadata(&path).unwrap();
+        let content = fs::read(&path).unwrap();
+        let leftovers = fs::read_dir(&dir).unwrap().count();
+        fs::remove_dir_all(&dir).unwrap();
+        assert_eq!(content, b"#!/bin/sh\necho\n");
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o754);
+        assert_eq!(metadata.modified().unwrap(), std::time::UNIX_EPOCH);
+        assert_eq!(leftovers, 1);
     }
 }